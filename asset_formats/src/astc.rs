@@ -0,0 +1,880 @@
+//! Astc LDR block compression
+//!
+//! Every block is 128 bits whatever its footprint, so larger footprints trade quality for a lower
+//! bit rate, from 8 bits per pixel at 4x4 down to 2 bits per pixel at 8x8. The encoder uses a
+//! single partition and weight plane with direct rgb or rgba endpoints, and stores blocks of a
+//! single color as void extent blocks.
+
+use crate::bcn::util::{
+    channel_tables, least_squares_endpoints, map_footprint, principal_endpoints, put_footprint,
+    texel_error, BitReader, BitWriter, ChannelTables,
+};
+use crate::color::ColorSpace;
+use crate::error::{Error, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Width and height of every 2D block footprint
+pub const FOOTPRINTS: [(u32, u32); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+/// Number of values endpoint colors can be quantized to, blocks need at least 6
+const COLOR_LEVELS: [u32; 17] = [
+    6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256,
+];
+
+/// Number of values weights can be quantized to, in the order block modes select them
+const WEIGHT_LEVELS: [u32; 12] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32];
+
+/// Bit patterns and multipliers unquantizing color values stored with a trit or quint
+///
+/// Letters are the bits stored below the trit or quint, `a` being the lowest.
+const COLOR_UNQUANTIZE: [(u32, &str, u32); 11] = [
+    (6, "000000000", 204),
+    (10, "000000000", 113),
+    (12, "b000b0bb0", 93),
+    (20, "b0000bb00", 54),
+    (24, "cb000cbcb", 44),
+    (40, "cb0000cbc", 26),
+    (48, "dcb000dcb", 22),
+    (80, "dcb0000dc", 13),
+    (96, "edcb000ed", 11),
+    (160, "edcb0000e", 6),
+    (192, "fedcb000f", 5),
+];
+
+/// Bit patterns and multipliers unquantizing weights stored with a trit or quint and some bits
+const WEIGHT_UNQUANTIZE: [(u32, &str, u32); 5] = [
+    (6, "0000000", 50),
+    (10, "0000000", 28),
+    (12, "b000b0b", 23),
+    (20, "b0000b0", 13),
+    (24, "cb000cb", 11),
+];
+
+/// Color endpoint mode of direct rgb endpoints
+const CEM_RGB: u32 = 8;
+/// Color endpoint mode of direct rgba endpoints
+const CEM_RGBA: u32 = 12;
+
+/// Color of texels in blocks the decoder doesn't support, the same as the spec's error color
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Astc compression block
+///
+/// An 11 bit block mode selecting the weight grid, the partition count and color endpoint mode,
+/// the integer sequence encoded endpoints and finally the weights, which are stored bit reversed
+/// from the top of the block.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct AstcBlock {
+    data: [u8; 16],
+}
+
+/// Encode an image
+///
+/// Blocks with transparent texels store rgba endpoints, fully opaque blocks only store rgb ones
+/// so they keep more precision for their colors.
+///
+/// * `footprint`: Width and height of a block, one of [`FOOTPRINTS`]
+pub fn encode(
+    img: &DynamicImage,
+    footprint: (u32, u32),
+    color_space: ColorSpace,
+) -> Result<Vec<AstcBlock>> {
+    check_footprint(footprint)?;
+    let img = img.to_rgba8();
+    let rgb = candidates(footprint, 6);
+    let rgba = candidates(footprint, 8);
+    // partial blocks are padded with the edge texels
+    Ok(map_footprint(&img, footprint, |block| {
+        let texels: Vec<[u8; 4]> = block.iter().map(|p| p.0).collect();
+        gen_block(&texels, &rgb, &rgba, color_space)
+    }))
+}
+
+/// Decode blocks generated by [`encode`] into an rgba image
+///
+/// Only the features used by the encoder are supported, blocks with several partitions, two weight
+/// planes or other endpoint modes decode to magenta.
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `footprint`: Width and height of a block
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+/// * `color_space`: Color space of the format, sRGB blocks interpolate with a different precision
+pub fn decode(
+    blocks: &[AstcBlock],
+    footprint: (u32, u32),
+    width: u32,
+    height: u32,
+    color_space: ColorSpace,
+) -> Result<DynamicImage> {
+    check_footprint(footprint)?;
+    let mut img = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(footprint.0);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels: Vec<Rgba<u8>> = decode_block(block, footprint, color_space)
+            .into_iter()
+            .map(Rgba)
+            .collect();
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_footprint(
+            &mut img,
+            x * footprint.0,
+            y * footprint.1,
+            footprint,
+            &texels,
+        );
+    }
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+/// Fail with [`Error::InvalidFootprint`] if a footprint isn't one of [`FOOTPRINTS`]
+fn check_footprint(footprint: (u32, u32)) -> Result<()> {
+    if !FOOTPRINTS.contains(&footprint) {
+        return Err(Error::InvalidFootprint(footprint.0, footprint.1));
+    }
+    Ok(())
+}
+
+/// Decode a single block into its texels in row-major order
+fn decode_block(block: &AstcBlock, footprint: (u32, u32), color_space: ColorSpace) -> Vec<[u8; 4]> {
+    let texels = (footprint.0 * footprint.1) as usize;
+    let bits = u128::from_le_bytes(block.data);
+    if bits & 0x1FF == 0x1FC {
+        // hdr void extent blocks store half floats
+        if bits >> 9 & 1 == 1 {
+            return vec![ERROR_COLOR; texels];
+        }
+        let color = std::array::from_fn(|c| to_8bit((bits >> (64 + 16 * c)) as u16, color_space));
+        return vec![color; texels];
+    }
+
+    let Some(mode) = BlockMode::new(bits as u32 & 0x7FF) else {
+        return vec![ERROR_COLOR; texels];
+    };
+    let count = match (bits >> 11 & 0x3, bits >> 13 & 0xF) {
+        (0, cem) if cem == CEM_RGB as u128 => 6,
+        (0, cem) if cem == CEM_RGBA as u128 => 8,
+        _ => return vec![ERROR_COLOR; texels],
+    };
+    let color_levels = match mode.color_levels(footprint, count) {
+        Some(levels) if !mode.dual_plane => levels,
+        _ => return vec![ERROR_COLOR; texels],
+    };
+
+    let mut reader = BitReader::new(block.data);
+    reader.read(17);
+    let table = color_table(color_levels);
+    let colors: Vec<u8> = Ise::new(color_levels)
+        .read(&mut reader, count)
+        .iter()
+        .map(|&v| table.values[v as usize])
+        .collect();
+    let (e0, e1) = decode_endpoints(&colors);
+
+    let mut reader = BitReader::new(bits.reverse_bits().to_le_bytes());
+    let table = weight_table(mode.levels);
+    let weights: Vec<u8> = Ise::new(mode.levels)
+        .read(&mut reader, mode.weight_count())
+        .iter()
+        .map(|&w| table.values[w as usize])
+        .collect();
+    infill(footprint, mode.grid)
+        .iter()
+        .map(|texel| interpolate(e0, e1, infill_weight(texel, &weights), color_space))
+        .collect()
+}
+
+/// Decode direct rgb or rgba endpoints from their unquantized values
+fn decode_endpoints(values: &[u8]) -> ([u8; 4], [u8; 4]) {
+    let alpha = |i: usize| values.get(i).copied().unwrap_or(255);
+    let e0 = [values[0], values[2], values[4], alpha(6)];
+    let e1 = [values[1], values[3], values[5], alpha(7)];
+    // a darker second endpoint means the endpoints are swapped and blue contracted
+    if color_sum(e1) >= color_sum(e0) {
+        (e0, e1)
+    } else {
+        (blue_contract(e1), blue_contract(e0))
+    }
+}
+
+fn color_sum(color: [u8; 4]) -> u32 {
+    color[..3].iter().map(|&c| c as u32).sum()
+}
+
+fn blue_contract([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    [
+        ((r as u32 + b as u32) >> 1) as u8,
+        ((g as u32 + b as u32) >> 1) as u8,
+        b,
+        a,
+    ]
+}
+
+/// Interpolate between two endpoints like an Astc decoder, `weight` going from 0 to 64
+fn interpolate(e0: [u8; 4], e1: [u8; 4], weight: u32, color_space: ColorSpace) -> [u8; 4] {
+    std::array::from_fn(|c| {
+        // endpoints are expanded to 16 bits before interpolating
+        let expand = |e: u8| match color_space {
+            ColorSpace::Linear => e as u32 * 257,
+            ColorSpace::Srgb => (e as u32) << 8 | 0x80,
+        };
+        let value = (expand(e0[c]) * (64 - weight) + expand(e1[c]) * weight + 32) >> 6;
+        to_8bit(value as u16, color_space)
+    })
+}
+
+/// Reduce an interpolated 16 bit value to 8 bits, sRGB decoders keep the top 8 bits
+fn to_8bit(value: u16, color_space: ColorSpace) -> u8 {
+    match color_space {
+        ColorSpace::Linear => ((value as u32 * 255 + 32767) / 65535) as u8,
+        ColorSpace::Srgb => (value >> 8) as u8,
+    }
+}
+
+/// Weight grid and weight quantization selected by the block mode of a block
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct BlockMode {
+    /// Width and height of the weight grid
+    grid: (u32, u32),
+    /// Number of values each weight can take
+    levels: u32,
+    /// Two weights per texel, the encoder never uses this
+    dual_plane: bool,
+}
+
+impl BlockMode {
+    /// Decode the layout of an 11 bit block mode, `None` for void extent and reserved modes
+    fn new(mode: u32) -> Option<Self> {
+        let bit = |i: u32| mode >> i & 1;
+        let (a, b) = (mode >> 5 & 3, mode >> 7 & 3);
+        let (grid, range, high, dual_plane) = if mode & 3 != 0 {
+            let grid = match mode >> 2 & 3 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 1 => (bit(7) + 2, a + 2),
+                _ => (a + 2, bit(7) + 6),
+            };
+            (grid, bit(4) | (mode & 3) << 1, bit(9), bit(10) == 1)
+        } else {
+            let range = bit(4) | (mode >> 2 & 3) << 1;
+            let grid = match (b, a) {
+                (0, _) => (12, a + 2),
+                (1, _) => (a + 2, 12),
+                // the precision and dual plane bits hold the height of the grid instead
+                (2, _) => return Self::with_range((a + 6, (mode >> 9 & 3) + 6), range, 0, false),
+                (_, 0) => (6, 10),
+                (_, 1) => (10, 6),
+                _ => return None,
+            };
+            (grid, range, bit(9), bit(10) == 1)
+        };
+        Self::with_range(grid, range, high, dual_plane)
+    }
+
+    fn with_range(grid: (u32, u32), range: u32, high: u32, dual_plane: bool) -> Option<Self> {
+        (range >= 2).then(|| BlockMode {
+            grid,
+            levels: WEIGHT_LEVELS[(range - 2 + 6 * high) as usize],
+            dual_plane,
+        })
+    }
+
+    /// Number of weights stored in a block
+    fn weight_count(&self) -> u32 {
+        self.grid.0 * self.grid.1 * if self.dual_plane { 2 } else { 1 }
+    }
+
+    /// Quantization of the `count` endpoint values of a single partition block with this mode
+    ///
+    /// returns `None` if the mode isn't valid for blocks of the given footprint
+    fn color_levels(&self, footprint: (u32, u32), count: u32) -> Option<u32> {
+        let weight_bits = Ise::new(self.levels).bit_count(self.weight_count());
+        if self.grid.0 > footprint.0
+            || self.grid.1 > footprint.1
+            || self.weight_count() > 64
+            || !(24..=96).contains(&weight_bits)
+        {
+            return None;
+        }
+        // dual plane blocks also store which channel their second plane holds
+        let available = 128 - 17 - weight_bits - if self.dual_plane { 2 } else { 0 };
+        COLOR_LEVELS
+            .iter()
+            .rev()
+            .copied()
+            .find(|&levels| Ise::new(levels).bit_count(count) <= available)
+    }
+}
+
+/// How values of a quantization level are stored by the integer sequence encoding
+#[derive(Debug, Copy, Clone)]
+enum Ise {
+    /// Values are stored as the given number of bits
+    Bits(u32),
+    /// Values are a trit above the given number of bits, the trits of 5 values are packed in 8 bits
+    Trits(u32),
+    /// Values are a quint above the given number of bits, the quints of 3 values are packed in 7
+    /// bits
+    Quints(u32),
+}
+
+impl Ise {
+    fn new(levels: u32) -> Self {
+        match levels {
+            _ if levels.is_multiple_of(3) => Ise::Trits((levels / 3).trailing_zeros()),
+            _ if levels.is_multiple_of(5) => Ise::Quints((levels / 5).trailing_zeros()),
+            _ => Ise::Bits(levels.trailing_zeros()),
+        }
+    }
+
+    /// Number of plain bits stored for each value
+    fn bits(&self) -> u32 {
+        match *self {
+            Ise::Bits(bits) | Ise::Trits(bits) | Ise::Quints(bits) => bits,
+        }
+    }
+
+    /// Bits of the packed trits or quints stored after each value of a group
+    fn packed_bits(&self) -> &'static [u32] {
+        match self {
+            Ise::Bits(_) => &[],
+            Ise::Trits(_) => &[2, 2, 1, 2, 1],
+            Ise::Quints(_) => &[3, 2, 2],
+        }
+    }
+
+    /// Number of bits needed to store `count` values
+    fn bit_count(&self, count: u32) -> u32 {
+        match *self {
+            Ise::Bits(bits) => bits * count,
+            Ise::Trits(bits) => bits * count + (8 * count).div_ceil(5),
+            Ise::Quints(bits) => bits * count + (7 * count).div_ceil(3),
+        }
+    }
+
+    /// Trits or quints of a group, unused digits are 0
+    fn unpack(&self, packed: u32) -> [u32; 5] {
+        match self {
+            Ise::Bits(_) => [0; 5],
+            Ise::Trits(_) => unpack_trits(packed),
+            Ise::Quints(_) => {
+                let [q0, q1, q2] = unpack_quints(packed);
+                [q0, q1, q2, 0, 0]
+            }
+        }
+    }
+
+    /// Pack the trits or quints of a group
+    fn pack(&self, digits: [u32; 5]) -> u32 {
+        static TRITS: OnceLock<Vec<u8>> = OnceLock::new();
+        static QUINTS: OnceLock<Vec<u8>> = OnceLock::new();
+        let (table, radix) = match self {
+            Ise::Bits(_) => return 0,
+            Ise::Trits(_) => (TRITS.get_or_init(|| self.packing(3, 256)), 3),
+            Ise::Quints(_) => (QUINTS.get_or_init(|| self.packing(5, 128)), 5),
+        };
+        table[digits.iter().rev().fold(0, |index, d| index * radix + d) as usize] as u32
+    }
+
+    /// Packed form of every group of trits or quints, indexed by their value in that base
+    fn packing(&self, radix: u32, packings: u32) -> Vec<u8> {
+        let groups = radix.pow(self.packed_bits().len() as u32);
+        let mut table = vec![0; groups as usize];
+        // several packings decode to the same digits, any of them works
+        for packed in 0..packings {
+            let index = self
+                .unpack(packed)
+                .iter()
+                .rev()
+                .fold(0, |index, d| index * radix + d);
+            table[index as usize] = packed as u8;
+        }
+        table
+    }
+
+    /// Write a sequence of encoded values
+    fn write(&self, writer: &mut BitWriter, values: &[u8]) {
+        let bits = self.bits();
+        let packed_bits = self.packed_bits();
+        if packed_bits.is_empty() {
+            for &value in values {
+                writer.write(value as u32, bits);
+            }
+            return;
+        }
+        for group in values.chunks(packed_bits.len()) {
+            let mut digits = [0; 5];
+            for (digit, &value) in digits.iter_mut().zip(group) {
+                *digit = value as u32 >> bits;
+            }
+            let mut packed = self.pack(digits);
+            for (&value, &len) in group.iter().zip(packed_bits) {
+                writer.write(value as u32, bits);
+                writer.write(packed, len);
+                packed >>= len;
+            }
+        }
+    }
+
+    /// Read a sequence of `count` encoded values
+    fn read(&self, reader: &mut BitReader, count: u32) -> Vec<u8> {
+        let bits = self.bits();
+        let packed_bits = self.packed_bits();
+        if packed_bits.is_empty() {
+            return (0..count).map(|_| reader.read(bits) as u8).collect();
+        }
+        let mut values = Vec::with_capacity(count as usize);
+        while values.len() < count as usize {
+            // the packed bits of missing values at the end of a sequence are zero
+            let len = packed_bits.len().min(count as usize - values.len());
+            let (mut low, mut packed, mut shift) = ([0; 5], 0, 0);
+            for (low, &bit_count) in low.iter_mut().zip(packed_bits).take(len) {
+                *low = reader.read(bits);
+                packed |= reader.read(bit_count) << shift;
+                shift += bit_count;
+            }
+            let digits = self.unpack(packed);
+            values.extend((0..len).map(|i| (digits[i] << bits | low[i]) as u8));
+        }
+        values
+    }
+}
+
+/// Bits `high` down to `low` of a value
+fn field(value: u32, high: u32, low: u32) -> u32 {
+    value >> low & ((1 << (high - low + 1)) - 1)
+}
+
+/// Unpack the 5 trits of a group from their 8 bit packed form
+fn unpack_trits(t: u32) -> [u32; 5] {
+    let (c, t3, t4) = if field(t, 4, 2) == 7 {
+        (field(t, 7, 5) << 2 | field(t, 1, 0), 2, 2)
+    } else if field(t, 6, 5) == 3 {
+        (field(t, 4, 0), field(t, 7, 7), 2)
+    } else {
+        (field(t, 4, 0), field(t, 6, 5), field(t, 7, 7))
+    };
+    let (t0, t1, t2) = if field(c, 1, 0) == 3 {
+        let t0 = field(c, 3, 3) << 1 | (field(c, 2, 2) & !field(c, 3, 3) & 1);
+        (t0, field(c, 4, 4), 2)
+    } else if field(c, 3, 2) == 3 {
+        (field(c, 1, 0), 2, 2)
+    } else {
+        let t0 = field(c, 1, 1) << 1 | (field(c, 0, 0) & !field(c, 1, 1) & 1);
+        (t0, field(c, 3, 2), field(c, 4, 4))
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+/// Unpack the 3 quints of a group from their 7 bit packed form
+fn unpack_quints(q: u32) -> [u32; 3] {
+    if field(q, 2, 1) == 3 && field(q, 6, 5) == 0 {
+        let not_q0 = !q & 1;
+        let q2 = field(q, 0, 0) << 2 | (field(q, 4, 4) & not_q0) << 1 | (field(q, 3, 3) & not_q0);
+        return [4, 4, q2];
+    }
+    let (c, q2) = if field(q, 2, 1) == 3 {
+        (
+            field(q, 4, 3) << 3 | (!field(q, 6, 5) & 3) << 1 | field(q, 0, 0),
+            4,
+        )
+    } else {
+        (field(q, 4, 0), field(q, 6, 5))
+    };
+    if field(c, 2, 0) == 5 {
+        [field(c, 4, 3), 4, q2]
+    } else {
+        [field(c, 2, 0), field(c, 4, 3), q2]
+    }
+}
+
+/// Unquantize an encoded value of a trit or quint level with its pattern from the spec
+///
+/// * `width`: Number of bits the pattern expands the value to before dropping the lowest 2
+fn unquantize(levels: u32, value: u32, patterns: &[(u32, &str, u32)], width: u32) -> u32 {
+    let bits = Ise::new(levels).bits();
+    let (_, pattern, c) = patterns.iter().find(|(l, ..)| *l == levels).unwrap();
+    let b = pattern.bytes().fold(0, |b, bit| {
+        b << 1
+            | if bit == b'0' {
+                0
+            } else {
+                value >> (bit - b'a') & 1
+            }
+    });
+    let a = if value & 1 == 1 { (1 << width) - 1 } else { 0 };
+    let t = ((value >> bits) * c + b) ^ a;
+    (a & 1 << (width - 2)) | t >> 2
+}
+
+/// Unquantize an encoded color value to 0..=255
+fn unquantize_color(levels: u32, value: u32) -> u32 {
+    match Ise::new(levels) {
+        Ise::Bits(bits) => replicate(value, bits, 8),
+        _ => unquantize(levels, value, &COLOR_UNQUANTIZE, 9),
+    }
+}
+
+/// Unquantize an encoded weight to 0..=64
+fn unquantize_weight(levels: u32, value: u32) -> u32 {
+    let weight = match (levels, Ise::new(levels)) {
+        (3, _) => [0, 32, 63][value as usize],
+        (5, _) => [0, 16, 32, 47, 63][value as usize],
+        (_, Ise::Bits(bits)) => replicate(value, bits, 6),
+        _ => unquantize(levels, value, &WEIGHT_UNQUANTIZE, 7),
+    };
+    // stretch 0..=63 to 0..=64 so the middle weight stays at 32
+    if weight > 32 {
+        weight + 1
+    } else {
+        weight
+    }
+}
+
+/// Repeat the bits of a value until they fill `width` bits
+fn replicate(value: u32, bits: u32, width: u32) -> u32 {
+    let (mut result, mut filled) = (0, 0);
+    while filled < width {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - width)
+}
+
+/// Unquantized value of every encoded value of a level, and the closest encoded value of every
+/// unquantized value
+struct QuantTable {
+    values: Vec<u8>,
+    nearest: Vec<u8>,
+}
+
+impl QuantTable {
+    fn new(levels: u32, max: u32, unquantize: impl Fn(u32) -> u32) -> Self {
+        let values: Vec<u8> = (0..levels).map(|v| unquantize(v) as u8).collect();
+        let nearest = (0..=max as i32)
+            .map(|target| {
+                (0..levels as usize)
+                    .min_by_key(|&v| (values[v] as i32 - target).abs())
+                    .unwrap() as u8
+            })
+            .collect();
+        Self { values, nearest }
+    }
+}
+
+fn color_table(levels: u32) -> &'static QuantTable {
+    static TABLES: OnceLock<Vec<QuantTable>> = OnceLock::new();
+    let tables = TABLES.get_or_init(|| {
+        COLOR_LEVELS
+            .iter()
+            .map(|&l| QuantTable::new(l, 255, |v| unquantize_color(l, v)))
+            .collect()
+    });
+    &tables[COLOR_LEVELS.iter().position(|&l| l == levels).unwrap()]
+}
+
+fn weight_table(levels: u32) -> &'static QuantTable {
+    static TABLES: OnceLock<Vec<QuantTable>> = OnceLock::new();
+    let tables = TABLES.get_or_init(|| {
+        WEIGHT_LEVELS
+            .iter()
+            .map(|&l| QuantTable::new(l, 64, |v| unquantize_weight(l, v)))
+            .collect()
+    });
+    &tables[WEIGHT_LEVELS.iter().position(|&l| l == levels).unwrap()]
+}
+
+/// Bilinear contributions of the weight grid to every texel of a footprint, in 16ths
+///
+/// returns the grid index and contribution of the 4 grid points around each texel
+fn infill(footprint: (u32, u32), grid: (u32, u32)) -> Vec<[(usize, u32); 4]> {
+    let (block_width, block_height) = footprint;
+    let (grid_width, grid_height) = grid;
+    let ds = (1024 + block_width / 2) / (block_width - 1);
+    let dt = (1024 + block_height / 2) / (block_height - 1);
+    (0..block_width * block_height)
+        .map(|i| {
+            let (s, t) = (i % block_width, i / block_width);
+            let gs = (ds * s * (grid_width - 1) + 32) >> 6;
+            let gt = (dt * t * (grid_height - 1) + 32) >> 6;
+            let (fs, ft) = (gs & 0xF, gt & 0xF);
+            let w11 = (fs * ft + 8) >> 4;
+            let v0 = ((gs >> 4) + (gt >> 4) * grid_width) as usize;
+            let row = grid_width as usize;
+            [
+                (v0, 16 + w11 - fs - ft),
+                (v0 + 1, fs - w11),
+                (v0 + row, ft - w11),
+                (v0 + row + 1, w11),
+            ]
+        })
+        .collect()
+}
+
+/// Weight of a texel from the unquantized weights of the grid
+fn infill_weight(texel: &[(usize, u32); 4], weights: &[u8]) -> u32 {
+    // grid points without a contribution can be past the edge of the grid
+    let sum: u32 = texel
+        .iter()
+        .filter(|(_, contribution)| *contribution > 0)
+        .map(|&(i, contribution)| weights[i] as u32 * contribution)
+        .sum();
+    (sum + 8) >> 4
+}
+
+/// A block mode the encoder can fit blocks to
+struct Candidate {
+    /// The 11 bit block mode
+    mode: u32,
+    grid: (u32, u32),
+    weight_levels: u32,
+    color_levels: u32,
+    /// Contributions of the grid to every texel, see [`infill`]
+    infill: Vec<[(usize, u32); 4]>,
+}
+
+/// Every distinct single plane block mode of a footprint that can store `count` endpoint values
+fn candidates(footprint: (u32, u32), count: u32) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = vec![];
+    for mode in 0..2048 {
+        let Some(block_mode) = BlockMode::new(mode).filter(|m| !m.dual_plane) else {
+            continue;
+        };
+        let Some(color_levels) = block_mode.color_levels(footprint, count) else {
+            continue;
+        };
+        // some grids and quantizations can be selected by several modes
+        if candidates
+            .iter()
+            .any(|c| c.grid == block_mode.grid && c.weight_levels == block_mode.levels)
+        {
+            continue;
+        }
+        candidates.push(Candidate {
+            mode,
+            grid: block_mode.grid,
+            weight_levels: block_mode.levels,
+            color_levels,
+            infill: infill(footprint, block_mode.grid),
+        });
+    }
+    candidates
+}
+
+/// Endpoints and weights of a block fitted to a candidate block mode
+struct Fit<'a> {
+    candidate: &'a Candidate,
+    /// Encoded endpoint values in the order they are stored, alpha is left out of rgb endpoints
+    colors: Vec<u8>,
+    /// Encoded weights of the grid
+    weights: Vec<u8>,
+    /// Weight of every texel after infill, from 0 to 64
+    texel_weights: Vec<u32>,
+    error: u32,
+}
+
+impl Fit<'_> {
+    fn block(&self) -> AstcBlock {
+        let cem = if self.colors.len() == 6 {
+            CEM_RGB
+        } else {
+            CEM_RGBA
+        };
+        let mut writer = BitWriter::new();
+        writer.write(self.candidate.mode, 11);
+        // a single partition
+        writer.write(0, 2);
+        writer.write(cem, 4);
+        Ise::new(self.candidate.color_levels).write(&mut writer, &self.colors);
+
+        let mut weights = BitWriter::new();
+        Ise::new(self.candidate.weight_levels).write(&mut weights, &self.weights);
+        let bits = u128::from_le_bytes(writer.finish())
+            | u128::from_le_bytes(weights.finish()).reverse_bits();
+        AstcBlock {
+            data: bits.to_le_bytes(),
+        }
+    }
+}
+
+/// Generate block
+///
+/// Principal axis endpoints are fitted to every candidate mode, and the endpoints of the best one
+/// are refined with least squares.
+///
+/// * `texels`: Texels of the block in row-major order
+/// * `rgb`: Candidate modes of blocks with rgb endpoints
+/// * `rgba`: Candidate modes of blocks with rgba endpoints
+fn gen_block(
+    texels: &[[u8; 4]],
+    rgb: &[Candidate],
+    rgba: &[Candidate],
+    color_space: ColorSpace,
+) -> AstcBlock {
+    if texels.iter().all(|t| *t == texels[0]) {
+        return void_extent(texels[0]);
+    }
+    let (candidates, channels) = if texels.iter().all(|t| t[3] == 255) {
+        (rgb, 0..3)
+    } else {
+        (rgba, 0..4)
+    };
+    let tables = channel_tables(color_space);
+    let fit = |candidate, endpoints| {
+        fit_block(
+            texels,
+            candidate,
+            endpoints,
+            channels.clone(),
+            color_space,
+            &tables,
+        )
+    };
+
+    let points: Vec<Vec4> = texels
+        .iter()
+        .map(|t| Vec4::from(t.map(f32::from)))
+        .collect();
+    let endpoints = principal_endpoints(&points);
+    let mut best = candidates
+        .iter()
+        .map(|candidate| fit(candidate, endpoints))
+        .min_by_key(|fit| fit.error)
+        .unwrap();
+    for _ in 0..2 {
+        let weighted = points
+            .iter()
+            .zip(&best.texel_weights)
+            .map(|(p, &weight)| (*p, weight as f32 / 64.0));
+        let Some(endpoints) = least_squares_endpoints(weighted) else {
+            break;
+        };
+        let refined = fit(best.candidate, endpoints);
+        if refined.error >= best.error {
+            break;
+        }
+        best = refined;
+    }
+    best.block()
+}
+
+/// A void extent block filling the whole block with a single color
+fn void_extent(color: [u8; 4]) -> AstcBlock {
+    // all ones extent coordinates mean the color doesn't extend past the block
+    let mut bits = 0xFFFF_FFFF_FFFF_FDFCu128;
+    for (c, &value) in color.iter().enumerate() {
+        bits |= (value as u128 * 257) << (64 + 16 * c);
+    }
+    AstcBlock {
+        data: bits.to_le_bytes(),
+    }
+}
+
+/// Fit a block to a candidate mode
+///
+/// The endpoints are quantized, the texels are projected onto them and their ideal weights are
+/// averaged onto the weight grid before being quantized.
+///
+/// * `endpoints`: Unquantized endpoints
+/// * `channels`: Channels stored by the endpoints, rgb endpoints are always opaque
+fn fit_block<'a>(
+    texels: &[[u8; 4]],
+    candidate: &'a Candidate,
+    endpoints: (Vec4, Vec4),
+    channels: Range<usize>,
+    color_space: ColorSpace,
+    tables: &ChannelTables,
+) -> Fit<'a> {
+    let table = color_table(candidate.color_levels);
+    let quantize = |e: Vec4| {
+        e.to_array()
+            .map(|c| table.nearest[c.round().clamp(0.0, 255.0) as usize])
+    };
+    let unquantize = |q: [u8; 4]| {
+        let mut e = q.map(|v| table.values[v as usize]);
+        if channels.end == 3 {
+            e[3] = 255;
+        }
+        e
+    };
+    let (mut q0, mut q1) = (quantize(endpoints.0), quantize(endpoints.1));
+    // keep the second endpoint brighter so decoders don't swap and blue contract them
+    if color_sum(unquantize(q1)) < color_sum(unquantize(q0)) {
+        std::mem::swap(&mut q0, &mut q1);
+    }
+    let (e0, e1) = (unquantize(q0), unquantize(q1));
+
+    let start = Vec4::from(e0.map(f32::from));
+    let axis = Vec4::from(e1.map(f32::from)) - start;
+    let mut sums = vec![(0.0, 0); (candidate.grid.0 * candidate.grid.1) as usize];
+    for (texel, contributions) in texels.iter().zip(&candidate.infill) {
+        let weight = if axis.length_squared() > 0.0 {
+            ((Vec4::from(texel.map(f32::from)) - start).dot(axis) / axis.length_squared())
+                .clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        for &(i, contribution) in contributions {
+            if contribution > 0 {
+                sums[i].0 += weight * contribution as f32;
+                sums[i].1 += contribution;
+            }
+        }
+    }
+    let weight_table = weight_table(candidate.weight_levels);
+    let weights: Vec<u8> = sums
+        .iter()
+        .map(|&(sum, total)| {
+            let weight = if total > 0 { sum / total as f32 } else { 0.0 };
+            weight_table.nearest[(weight * 64.0).round() as usize]
+        })
+        .collect();
+    let grid: Vec<u8> = weights
+        .iter()
+        .map(|&w| weight_table.values[w as usize])
+        .collect();
+
+    let texel_weights: Vec<u32> = candidate
+        .infill
+        .iter()
+        .map(|texel| infill_weight(texel, &grid))
+        .collect();
+    let error = texels
+        .iter()
+        .zip(&texel_weights)
+        .map(|(texel, &weight)| {
+            let decoded = interpolate(e0, e1, weight, color_space);
+            texel_error(&decoded, texel, channels.clone(), tables)
+        })
+        .sum();
+
+    let colors = [q0[0], q1[0], q0[1], q1[1], q0[2], q1[2], q0[3], q1[3]];
+    Fit {
+        candidate,
+        colors: colors[..channels.end * 2].to_vec(),
+        weights,
+        texel_weights,
+        error,
+    }
+}
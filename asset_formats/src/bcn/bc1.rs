@@ -0,0 +1,267 @@
+use crate::bcn::util;
+use crate::bcn::util::{channel_tables, map_blocks, put_block, texel_error, ChannelTables};
+use crate::bcn::Quality;
+use crate::color::ColorSpace;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Bc1 Compression block
+///
+/// When `color0 > color1` the block interpolates 4 opaque colors, otherwise it interpolates 3 opaque
+/// colors and uses index 3 for transparent black.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Bc1Block {
+    color0: u16,
+    color1: u16,
+    indices: u32,
+}
+
+/// Encode an opaque image, the alpha channel is ignored
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode(img: &DynamicImage, color_space: ColorSpace, quality: Quality) -> Vec<Bc1Block> {
+    encode_blocks(img, false, color_space, quality)
+}
+
+/// Encode an image with 1 bit alpha, texels with an alpha below 128 become transparent black
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode_alpha(
+    img: &DynamicImage,
+    color_space: ColorSpace,
+    quality: Quality,
+) -> Vec<Bc1Block> {
+    encode_blocks(img, true, color_space, quality)
+}
+
+fn encode_blocks(
+    img: &DynamicImage,
+    alpha: bool,
+    color_space: ColorSpace,
+    quality: Quality,
+) -> Vec<Bc1Block> {
+    let tables = channel_tables(color_space);
+    let img = img.to_rgba8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        gen_block(&block.map(|p| p.0), alpha, quality, &tables)
+    })
+}
+
+/// Decode a list of Bc1 blocks back into an rgba image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode(blocks: &[Bc1Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_block(block, block.color0 > block.color1).map(Rgba);
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Decode a single block into its 16 texels
+///
+/// * `four_color`: Whether the block uses 4 opaque colors, Bc2 and Bc3 color blocks always do
+pub(super) fn decode_block(block: &Bc1Block, four_color: bool) -> [[u8; 4]; 16] {
+    let pal = palette(block.color0, block.color1, four_color);
+    std::array::from_fn(|px| pal[((block.indices >> (px * 2)) & 0x3) as usize])
+}
+
+/// Generate block
+///
+/// * `texels`: Rgba texels of the block in row-major order
+/// * `alpha`: Whether texels with an alpha below 128 should be made transparent
+/// * `tables`: Linear value of each channel of the texels
+pub(super) fn gen_block(
+    texels: &[[u8; 4]; 16],
+    alpha: bool,
+    quality: Quality,
+    tables: &ChannelTables,
+) -> Bc1Block {
+    let transparent = texels.map(|t| alpha && t[3] < 128);
+    let opaque: Vec<Vec4> = texels
+        .iter()
+        .zip(transparent)
+        .filter(|(_, transparent)| !transparent)
+        .map(|(t, _)| to_point(t))
+        .collect();
+
+    if opaque.is_empty() {
+        return Bc1Block {
+            color0: 0,
+            color1: 0,
+            indices: u32::MAX,
+        };
+    }
+
+    let endpoints = util::principal_endpoints(&opaque);
+
+    // only the 3 color mode can store transparent texels, otherwise try both modes and keep the best
+    let three_color = fit_block(endpoints, texels, &transparent, false, quality, tables);
+    if transparent.contains(&true) {
+        return three_color.0;
+    }
+    let four_color = fit_block(endpoints, texels, &transparent, true, quality, tables);
+    // equal endpoints are always decoded with 3 colors
+    if four_color.0.color0 > four_color.0.color1 && four_color.1 <= three_color.1 {
+        four_color.0
+    } else {
+        three_color.0
+    }
+}
+
+/// Generate a color block that is always decoded with 4 colors, as used by Bc2 and Bc3
+///
+/// * `texels`: Rgba texels of the block in row-major order, alpha is ignored
+/// * `tables`: Linear value of each channel of the texels
+pub(super) fn gen_four_color_block(
+    texels: &[[u8; 4]; 16],
+    quality: Quality,
+    tables: &ChannelTables,
+) -> Bc1Block {
+    let rgb: Vec<Vec4> = texels.iter().map(to_point).collect();
+    let endpoints = util::principal_endpoints(&rgb);
+    fit_block(endpoints, texels, &[false; 16], true, quality, tables).0
+}
+
+/// Fit a block to a pair of endpoints, then refine them with least squares against the indices
+/// the texels map to
+///
+/// returns the block and its total squared error
+fn fit_block(
+    endpoints: (Vec4, Vec4),
+    texels: &[[u8; 4]; 16],
+    transparent: &[bool; 16],
+    four_color: bool,
+    quality: Quality,
+    tables: &ChannelTables,
+) -> (Bc1Block, u32) {
+    // weight of color1 in each palette entry
+    let weights = if four_color {
+        [0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0]
+    } else {
+        [0.0, 1.0, 0.5, 0.0]
+    };
+    let quantize = |(a, b): (Vec4, Vec4)| {
+        let (a, b) = (to_565(to_rgb8(a)), to_565(to_rgb8(b)));
+        // the order of the endpoints picks the mode of the block
+        let (color0, color1) = if four_color {
+            (a.max(b), a.min(b))
+        } else {
+            (a.min(b), a.max(b))
+        };
+        map_block(color0, color1, texels, transparent, four_color, tables)
+    };
+
+    let mut best = quantize(endpoints);
+    for _ in 0..quality.refinements() {
+        let points = texels
+            .iter()
+            .zip(transparent)
+            .enumerate()
+            .filter(|(_, (_, transparent))| !**transparent)
+            .map(|(px, (texel, _))| {
+                let idx = (best.0.indices >> (px * 2)) & 0x3;
+                (to_point(texel), weights[idx as usize])
+            });
+        let Some(endpoints) = util::least_squares_endpoints(points) else {
+            break;
+        };
+        let refined = quantize(endpoints);
+        if refined.1 >= best.1 {
+            break;
+        }
+        best = refined;
+    }
+    best
+}
+
+/// The rgb channels of a texel as a point to fit endpoints to
+fn to_point(texel: &[u8; 4]) -> Vec4 {
+    Vec4::new(texel[0] as f32, texel[1] as f32, texel[2] as f32, 0.0)
+}
+
+/// Round a fitted endpoint to an 8 bit rgb color
+fn to_rgb8(c: Vec4) -> [u8; 3] {
+    [c.x, c.y, c.z].map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Map every texel of a block to its closest palette entry
+///
+/// returns the block and its total squared error
+fn map_block(
+    color0: u16,
+    color1: u16,
+    texels: &[[u8; 4]; 16],
+    transparent: &[bool; 16],
+    four_color: bool,
+    tables: &ChannelTables,
+) -> (Bc1Block, u32) {
+    let pal = palette(color0, color1, four_color);
+    let colors = if four_color { 4 } else { 3 };
+    let mut indices = 0u32;
+    let mut error = 0;
+    for (px, (texel, transparent)) in texels.iter().zip(transparent).enumerate() {
+        if *transparent {
+            indices |= 3 << (px * 2);
+            continue;
+        }
+        let (idx, err) = pal[..colors]
+            .iter()
+            .map(|c| texel_error(c, texel, 0..3, tables))
+            .enumerate()
+            .min_by_key(|(_, err)| *err)
+            .unwrap();
+        indices |= (idx as u32) << (px * 2);
+        error += err;
+    }
+    (
+        Bc1Block {
+            color0,
+            color1,
+            indices,
+        },
+        error,
+    )
+}
+
+/// Generate the 4 entry palette of a block from its endpoints
+fn palette(color0: u16, color1: u16, four_color: bool) -> [[u8; 4]; 4] {
+    let a = from_565(color0);
+    let b = from_565(color1);
+    let mix = |wa: u16, wb: u16| -> [u8; 4] {
+        let c = |i: usize| ((wa * a[i] as u16 + wb * b[i] as u16) / (wa + wb)) as u8;
+        [c(0), c(1), c(2), 255]
+    };
+    if four_color {
+        [a, b, mix(2, 1), mix(1, 2)]
+    } else {
+        [a, b, mix(1, 1), [0; 4]]
+    }
+}
+
+/// Quantize an rgb color to 5:6:5 bits
+pub(super) fn to_565(c: [u8; 3]) -> u16 {
+    let q = |v: u8, max: u16| (v as u16 * max + 127) / 255;
+    (q(c[0], 31) << 11) | (q(c[1], 63) << 5) | q(c[2], 31)
+}
+
+/// Expand a 5:6:5 color back to 8 bits per channel
+pub(super) fn from_565(c: u16) -> [u8; 4] {
+    let r = ((c >> 11) & 0x1F) as u8;
+    let g = ((c >> 5) & 0x3F) as u8;
+    let b = (c & 0x1F) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
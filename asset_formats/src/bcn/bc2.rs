@@ -0,0 +1,60 @@
+use crate::bcn::bc1;
+use crate::bcn::bc1::Bc1Block;
+use crate::bcn::util::{channel_tables, map_blocks, put_block};
+use crate::bcn::Quality;
+use crate::color::ColorSpace;
+use bytemuck::{Pod, Zeroable};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Bc2 Compression block
+///
+/// An explicit 4 bit alpha value for every texel followed by a [`Bc1Block`] that is always decoded
+/// with 4 colors. Mostly found in legacy `DXT3` DDS files, [`Bc3Block`](super::bc3::Bc3Block)
+/// stores smooth alpha gradients better.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Bc2Block {
+    alpha: [u8; 8],
+    color: Bc1Block,
+}
+
+/// Encode an rgba image
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode(img: &DynamicImage, color_space: ColorSpace, quality: Quality) -> Vec<Bc2Block> {
+    let tables = channel_tables(color_space);
+    let img = img.to_rgba8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        let texels = block.map(|p| p.0);
+        let mut alpha = 0u64;
+        for (px, texel) in texels.iter().enumerate() {
+            // round to the nearest of the 16 levels, 17 being 255 / 15
+            alpha |= (((texel[3] as u64) + 8) / 17) << (px * 4);
+        }
+        Bc2Block {
+            alpha: alpha.to_le_bytes(),
+            color: bc1::gen_four_color_block(&texels, quality, &tables),
+        }
+    })
+}
+
+/// Decode a list of Bc2 blocks back into an rgba image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode(blocks: &[Bc2Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let alpha = u64::from_le_bytes(block.alpha);
+        let mut texels = bc1::decode_block(&block.color, true);
+        for (px, texel) in texels.iter_mut().enumerate() {
+            texel[3] = ((alpha >> (px * 4)) & 0xF) as u8 * 17;
+        }
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels.map(Rgba));
+    }
+    DynamicImage::ImageRgba8(img)
+}
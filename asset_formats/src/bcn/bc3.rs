@@ -0,0 +1,55 @@
+use crate::bcn::bc1::Bc1Block;
+use crate::bcn::bc4::Bc4Block;
+use crate::bcn::util::{channel_tables, map_blocks, put_block};
+use crate::bcn::Quality;
+use crate::bcn::{bc1, bc4};
+use crate::color::ColorSpace;
+use bytemuck::{Pod, Zeroable};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Bc3 Compression block
+///
+/// An alpha channel stored like a [`Bc4Block`] followed by a [`Bc1Block`] that is always decoded
+/// with 4 colors.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Bc3Block {
+    alpha: Bc4Block,
+    color: Bc1Block,
+}
+
+/// Encode an rgba image
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode(img: &DynamicImage, color_space: ColorSpace, quality: Quality) -> Vec<Bc3Block> {
+    let tables = channel_tables(color_space);
+    let img = img.to_rgba8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        let texels = block.map(|p| p.0);
+        Bc3Block {
+            alpha: bc4::gen_block(texels.map(|t| t[3]), quality),
+            color: bc1::gen_four_color_block(&texels, quality, &tables),
+        }
+    })
+}
+
+/// Decode a list of Bc3 blocks back into an rgba image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode(blocks: &[Bc3Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let alpha = bc4::decode_block(&block.alpha);
+        let mut texels = bc1::decode_block(&block.color, true);
+        for (texel, alpha) in texels.iter_mut().zip(alpha) {
+            texel[3] = alpha;
+        }
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels.map(Rgba));
+    }
+    DynamicImage::ImageRgba8(img)
+}
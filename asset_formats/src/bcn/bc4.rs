@@ -1,8 +1,10 @@
-use crate::bcn::util::avg_error;
+use crate::bcn::util::{
+    avg_error, generate_palette, least_squares_endpoints, map_blocks, map_color, put_block,
+};
+use crate::bcn::Quality;
 use bytemuck::{Pod, Zeroable};
-use image::imageops::FilterType;
-use image::{DynamicImage, EncodableLayout};
-use log::{info, warn};
+use glam::Vec4;
+use image::{DynamicImage, GrayImage, Luma};
 
 /// Bc4 Compression block
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
@@ -13,53 +15,181 @@ pub struct Bc4Block {
     data: [u8; 6],
 }
 
-pub fn encode(img: &DynamicImage) -> Vec<Bc4Block> {
-    let w = (img.width() / 4) * 4;
-    let h = (img.height() / 4) * 4;
-    let img = img.resize_exact(w, h, FilterType::Nearest);
-    let mut blocks = vec![];
-    for y in (0..h).step_by(4) {
-        for x in (0..w).step_by((4)) {
-            // grab cutout of this block
-            let block = img.crop_imm(x, y, 4, 4).to_luma8();
-            let mut bytes = [0u8; 16];
-            bytes.copy_from_slice(block.as_bytes());
-            let block = gen_block(bytes);
-            blocks.push(block);
-        }
+pub fn encode(img: &DynamicImage, quality: Quality) -> Vec<Bc4Block> {
+    let img = img.to_luma8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| gen_block(block.map(|p| p[0]), quality))
+}
+
+/// Encode the luma of an image as `DXGI_FORMAT_BC4_SNORM`, mapping it from 0..1 to -1..1
+pub fn encode_signed(img: &DynamicImage, quality: Quality) -> Vec<Bc4Block> {
+    let img = img.to_luma32f();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        gen_signed_block(block.map(|p| to_snorm(p[0] * 2.0 - 1.0)), quality)
+    })
+}
+
+/// Decode a list of Bc4 blocks back into a grayscale image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode(blocks: &[Bc4Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = GrayImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_block(block).map(|l| Luma([l]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageLuma8(img)
+}
+
+/// Decode blocks generated by [`encode_signed`] into a grayscale image
+///
+/// Values are mapped back from -1..1 to 0..1.
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_signed(blocks: &[Bc4Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = GrayImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_signed_block(block).map(|v| Luma([to_unsigned(v)]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
     }
-    blocks
+    DynamicImage::ImageLuma8(img)
+}
+
+/// Quantize a value in -1..1 to a signed normalized byte
+pub(super) fn to_snorm(value: f32) -> i8 {
+    (value.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+/// Expand a signed normalized byte to -1..1, -128 decodes to -1 like -127 does
+pub(super) fn from_snorm(value: i8) -> f32 {
+    (value as f32 / 127.0).max(-1.0)
+}
+
+/// Map a signed normalized byte from -1..1 back to an unsigned byte
+pub(super) fn to_unsigned(value: i8) -> u8 {
+    ((from_snorm(value) * 0.5 + 0.5) * 255.0).round() as u8
+}
+
+/// Decode a single block into its 16 values
+pub(super) fn decode_block(block: &Bc4Block) -> [u8; 16] {
+    let pal = interpolate(block.a, block.b);
+    let mut bytes = [0u8; 8];
+    bytes[0..6].copy_from_slice(&block.data);
+    let data = u64::from_le_bytes(bytes);
+    std::array::from_fn(|px| pal[((data >> (px * 3)) & 0x7) as usize])
 }
 
 /// Generate block
-pub(super) fn gen_block(block: [u8; 16]) -> Bc4Block {
-    // first get a palette
-    let end_points = palette(&block);
-    let pal = interpolate(end_points.0, end_points.1);
-    let mut data = 0u64;
-    for (px, c) in block.iter().enumerate() {
-        let mut best_fit = (0, pal[0]);
-        for (i, pal) in pal.iter().enumerate() {
-            if pal.abs_diff(*c) < best_fit.1.abs_diff(*c) {
-                best_fit = (i, *pal);
+pub(super) fn gen_block(block: [u8; 16], quality: Quality) -> Bc4Block {
+    fit_block(block, (0, 255), quality, |a, b| {
+        interpolate(a as u8, b as u8).map(i32::from)
+    })
+}
+
+/// Decode a single `DXGI_FORMAT_BC4_SNORM` block into its 16 values
+pub(super) fn decode_signed_block(block: &Bc4Block) -> [i8; 16] {
+    let pal = interpolate_signed(block.a as i8, block.b as i8);
+    let mut bytes = [0u8; 8];
+    bytes[0..6].copy_from_slice(&block.data);
+    let data = u64::from_le_bytes(bytes);
+    std::array::from_fn(|px| pal[((data >> (px * 3)) & 0x7) as usize])
+}
+
+/// Generate a `DXGI_FORMAT_BC4_SNORM` block, -128 is clamped to -127 like the hardware does
+pub(super) fn gen_signed_block(block: [i8; 16], quality: Quality) -> Bc4Block {
+    fit_block(block.map(|v| v.max(-127)), (-127, 127), quality, |a, b| {
+        interpolate_signed(a as i8, b as i8).map(i32::from)
+    })
+}
+
+/// Fit the endpoints of a block with both palette modes and keep the one with the lowest error
+///
+/// The 8 value mode spans the values of the block, the 6 value mode spans the values between the
+/// extremes of `range` since those are part of its palette already.
+///
+/// * `range`: Smallest and largest value an endpoint can hold
+/// * `palette`: Palette of a pair of endpoints, signed endpoints are passed as negative values
+fn fit_block<T: Into<f32> + Copy>(
+    block: [T; 16],
+    range: (i32, i32),
+    quality: Quality,
+    palette: impl Fn(i32, i32) -> [i32; 8],
+) -> Bc4Block {
+    let values = block.map(|v| v.into() as i32);
+    let inner: Vec<T> = block
+        .iter()
+        .filter(|v| ![range.0, range.1].contains(&((**v).into() as i32)))
+        .copied()
+        .collect();
+    let (min, max) = generate_palette(&block);
+    let (inner_min, inner_max) = generate_palette(&inner);
+
+    // the first endpoint is the larger one in the 8 value mode and the smaller one otherwise
+    let (a, b) = [(max, min), (inner_min, inner_max)]
+        .into_iter()
+        .map(|(a, b)| {
+            let eight = a > b;
+            let mut best = (a as i32, b as i32);
+            let mut error = avg_error(&values, &palette(best.0, best.1));
+            for _ in 0..quality.refinements() {
+                let pal = palette(best.0, best.1);
+                let points = values.iter().filter_map(|v| {
+                    let weight = weight(map_color(*v, &pal).0, eight)?;
+                    Some((Vec4::splat(*v as f32), weight))
+                });
+                let Some((a, b)) = least_squares_endpoints(points) else {
+                    break;
+                };
+                let clamp = |v: Vec4| (v.x.round() as i32).clamp(range.0, range.1);
+                let (a, b) = (clamp(a), clamp(b));
+                // keep the mode the endpoints were fitted for
+                let refined = if (a > b) == eight { (a, b) } else { (b, a) };
+                let refined_error = avg_error(&values, &palette(refined.0, refined.1));
+                if refined_error >= error {
+                    break;
+                }
+                (best, error) = (refined, refined_error);
             }
-        }
-        data = data | ((best_fit.0 & 0x7) << (px * 3)) as u64;
+            (best, error)
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap()
+        .0;
+
+    let pal = palette(a, b);
+    let mut data = 0u64;
+    for (px, v) in values.iter().enumerate() {
+        data |= (map_color(*v, &pal).0 as u64) << (px * 3);
     }
     let mut bytes = [0u8; 6];
-    bytes.copy_from_slice(&data.to_ne_bytes()[0..6]);
+    bytes.copy_from_slice(&data.to_le_bytes()[0..6]);
 
     Bc4Block {
-        a: end_points.0,
-        b: end_points.1,
+        a: a as u8,
+        b: b as u8,
         data: bytes,
     }
 }
 
-/// Get Optimal palette for a set of colors
-fn palette(colors: &[u8]) -> (u8, u8) {
-    let pal = super::util::generate_palette(colors);
-    (pal.1 as u8, pal.0 as u8)
+/// Weight of the second endpoint in a palette entry, `None` for the fixed entries of the 6 value
+/// mode
+fn weight(idx: usize, eight: bool) -> Option<f32> {
+    match (idx, eight) {
+        (0, _) => Some(0.0),
+        (1, _) => Some(1.0),
+        (_, true) => Some((idx - 1) as f32 / 7.0),
+        (2..=5, false) => Some((idx - 1) as f32 / 5.0),
+        _ => None,
+    }
 }
 
 /// Generate color palette for a block based on start and end colors
@@ -96,3 +226,37 @@ fn interpolate(a: u8, b: u8) -> [u8; 8] {
         ]
     }
 }
+
+/// Generate the signed palette of a block based on start and end values
+///
+/// Unlike the unsigned palette the interpolated values are rounded, and the 6 value mode ends with
+/// -1 and 1.
+fn interpolate_signed(a: i8, b: i8) -> [i8; 8] {
+    fn pol(a: i8, b: i8, i: i32, max: i32) -> i8 {
+        (((max - i) * a as i32 + i * b as i32) as f32 / max as f32).round() as i8
+    }
+
+    if a > b {
+        [
+            a,
+            b,
+            pol(a, b, 1, 7),
+            pol(a, b, 2, 7),
+            pol(a, b, 3, 7),
+            pol(a, b, 4, 7),
+            pol(a, b, 5, 7),
+            pol(a, b, 6, 7),
+        ]
+    } else {
+        [
+            a,
+            b,
+            pol(a, b, 1, 5),
+            pol(a, b, 2, 5),
+            pol(a, b, 3, 5),
+            pol(a, b, 4, 5),
+            -127,
+            127,
+        ]
+    }
+}
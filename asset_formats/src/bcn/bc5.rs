@@ -1,8 +1,12 @@
-use crate::bcn::bc4::{gen_block, Bc4Block};
+use crate::bcn::bc4::{
+    decode_block, decode_signed_block, from_snorm, gen_block, gen_signed_block, to_snorm,
+    to_unsigned, Bc4Block,
+};
+use crate::bcn::util::{map_blocks, put_block};
+use crate::bcn::Quality;
 use bytemuck::{Pod, Zeroable};
-use image::imageops::FilterType;
-use image::DynamicImage;
-use std::io::Read;
+use glam::{Vec2, Vec3};
+use image::{DynamicImage, GrayAlphaImage, LumaA, Rgb, RgbImage};
 
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
@@ -11,52 +15,164 @@ pub struct Bc5Block {
     a: Bc4Block,
 }
 
-pub fn encode_grayscale(img: &DynamicImage) -> Vec<Bc5Block> {
-    let w = (img.width() / 4) * 4;
-    let h = (img.height() / 4) * 4;
-    let img = img.resize_exact(w, h, FilterType::Nearest);
-    let mut blocks = vec![];
-    for y in (0..h).step_by(4) {
-        for x in (0..w).step_by((4)) {
-            // grab cutout of this block
-            let block = img.crop_imm(x, y, 4, 4).to_luma_alpha8();
-            let mut gray_bytes = [0u8; 16];
-            let mut alpha_bytes = [0u8; 16];
-            for (i, pixel) in block.chunks(2).enumerate() {
-                gray_bytes[i] = pixel[0];
-                alpha_bytes[i] = pixel[1];
-            }
+pub fn encode_grayscale(img: &DynamicImage, quality: Quality) -> Vec<Bc5Block> {
+    let img = img.to_luma_alpha8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| Bc5Block {
+        l: gen_block(block.map(|p| p[0]), quality),
+        a: gen_block(block.map(|p| p[1]), quality),
+    })
+}
+
+pub fn encode_color(img: &DynamicImage, quality: Quality) -> Vec<Bc5Block> {
+    let img = img.to_rgb8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| Bc5Block {
+        l: gen_block(block.map(|p| p[0]), quality),
+        a: gen_block(block.map(|p| p[1]), quality),
+    })
+}
+
+/// Encode the red and green channels of an image as `DXGI_FORMAT_BC5_SNORM`, mapping them from
+/// 0..1 to -1..1
+pub fn encode_signed(img: &DynamicImage, quality: Quality) -> Vec<Bc5Block> {
+    let img = img.to_rgb32f();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| Bc5Block {
+        l: gen_signed_block(block.map(|p| to_snorm(p[0] * 2.0 - 1.0)), quality),
+        a: gen_signed_block(block.map(|p| to_snorm(p[1] * 2.0 - 1.0)), quality),
+    })
+}
 
-            blocks.push(Bc5Block {
-                l: gen_block(gray_bytes),
-                a: gen_block(alpha_bytes),
-            });
+/// Encode a tangent space normal map, storing the X and Y of each normal
+///
+/// The rgb channels of the image hold normals mapped from -1..1 to 0..1. Every normal is
+/// renormalized before X and Y are stored, Z can be rebuilt with [`decode_normals`].
+///
+/// * `signed`: Store X and Y as is for `DXGI_FORMAT_BC5_SNORM`, otherwise they are mapped back to
+///   0..1 for `DXGI_FORMAT_BC5_UNORM`
+pub fn encode_normals(img: &DynamicImage, signed: bool, quality: Quality) -> Vec<Bc5Block> {
+    let img = img.to_rgb32f();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        let normals = block.map(|p| {
+            let normal = Vec3::from(p.0) * 2.0 - 1.0;
+            normal.try_normalize().unwrap_or(Vec3::Z).truncate()
+        });
+        if signed {
+            Bc5Block {
+                l: gen_signed_block(normals.map(|n| to_snorm(n.x)), quality),
+                a: gen_signed_block(normals.map(|n| to_snorm(n.y)), quality),
+            }
+        } else {
+            let quantize = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+            Bc5Block {
+                l: gen_block(normals.map(|n| quantize(n.x)), quality),
+                a: gen_block(normals.map(|n| quantize(n.y)), quality),
+            }
         }
+    })
+}
+
+/// Decode blocks generated by [`encode_grayscale`] into a grayscale image with alpha
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_grayscale(blocks: &[Bc5Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = GrayAlphaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let gray = decode_block(&block.l);
+        let alpha = decode_block(&block.a);
+        let texels = std::array::from_fn(|px| LumaA([gray[px], alpha[px]]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
     }
-    blocks
-}
-
-pub fn encode_color(img: &DynamicImage) -> Vec<Bc5Block> {
-    let w = (img.width() / 4) * 4;
-    let h = (img.height() / 4) * 4;
-    let img = img.resize_exact(w, h, FilterType::Nearest);
-    let mut blocks = vec![];
-    for y in (0..h).step_by(4) {
-        for x in (0..w).step_by((4)) {
-            // grab cutout of this block
-            let block = img.crop_imm(x, y, 4, 4).to_rgb8();
-            let mut red = [0u8; 16];
-            let mut green = [0u8; 16];
-            for (i, pixel) in block.chunks(3).enumerate() {
-                red[i] = pixel[0];
-                green[i] = pixel[1];
-            }
+    DynamicImage::ImageLumaA8(img)
+}
 
-            blocks.push(Bc5Block {
-                l: gen_block(red),
-                a: gen_block(green),
-            });
-        }
+/// Decode blocks generated by [`encode_color`] into an rgb image
+///
+/// The blue channel is not stored in the block so it is always zero.
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_color(blocks: &[Bc5Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let red = decode_block(&block.l);
+        let green = decode_block(&block.a);
+        let texels = std::array::from_fn(|px| Rgb([red[px], green[px], 0]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageRgb8(img)
+}
+
+/// Decode blocks generated by [`encode_signed`] into an rgb image
+///
+/// Values are mapped back from -1..1 to 0..1, the blue channel is not stored in the block so it is
+/// always zero.
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_signed(blocks: &[Bc5Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let red = decode_signed_block(&block.l);
+        let green = decode_signed_block(&block.a);
+        let texels =
+            std::array::from_fn(|px| Rgb([to_unsigned(red[px]), to_unsigned(green[px]), 0]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
     }
-    blocks
+    DynamicImage::ImageRgb8(img)
+}
+
+/// Decode blocks generated by [`encode_normals`] into an rgb normal map
+///
+/// Z is rebuilt from the stored X and Y, and the normals are mapped back from -1..1 to 0..1.
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+/// * `signed`: The blocks were encoded for `DXGI_FORMAT_BC5_SNORM`
+pub fn decode_normals(blocks: &[Bc5Block], width: u32, height: u32, signed: bool) -> DynamicImage {
+    let mut img = RgbImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let (x, y) = if signed {
+            (
+                decode_signed_block(&block.l).map(from_snorm),
+                decode_signed_block(&block.a).map(from_snorm),
+            )
+        } else {
+            let unpack = |v: u8| v as f32 / 255.0 * 2.0 - 1.0;
+            (
+                decode_block(&block.l).map(unpack),
+                decode_block(&block.a).map(unpack),
+            )
+        };
+        let texels = std::array::from_fn(|px| {
+            let normal = reconstruct_z(Vec2::new(x[px], y[px]));
+            Rgb(((normal * 0.5 + 0.5) * 255.0)
+                .round()
+                .to_array()
+                .map(|c| c as u8))
+        });
+        let (bx, by) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, bx * 4, by * 4, &texels);
+    }
+    DynamicImage::ImageRgb8(img)
+}
+
+/// Rebuild a unit normal from its X and Y, Z always points out of the surface
+pub fn reconstruct_z(xy: Vec2) -> Vec3 {
+    let xy = xy.clamp_length_max(1.0);
+    xy.extend((1.0 - xy.length_squared()).max(0.0).sqrt())
 }
@@ -0,0 +1,362 @@
+//! Bc6h compression of half float rgb images
+//!
+//! Only the single region modes (11 through 14) are generated. Mode 11 stores both endpoints
+//! directly with 10 bits per channel, modes 12 to 14 trade precision of the second endpoint for
+//! precision of the first by storing it as a delta. Every mode is tried on each block and the one
+//! with the lowest error is kept.
+
+use crate::bcn::util::{map_blocks, put_block, BitReader, BitWriter, WEIGHTS4};
+use bytemuck::{Pod, Zeroable};
+use half::f16;
+use image::{DynamicImage, Rgb, Rgb32FImage};
+
+/// Bc6h Compression block
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Bc6hBlock {
+    data: [u8; 16],
+}
+
+/// A single region block mode
+struct Mode {
+    /// Mode bits stored at the start of the block
+    id: u32,
+    /// Precision of the first endpoint
+    endpoint_bits: u32,
+    /// Precision of the second endpoint's offset from the first, `None` if it is stored directly
+    delta_bits: Option<u32>,
+}
+
+const MODES: [Mode; 4] = [
+    Mode {
+        id: 0x03,
+        endpoint_bits: 10,
+        delta_bits: None,
+    },
+    Mode {
+        id: 0x07,
+        endpoint_bits: 11,
+        delta_bits: Some(9),
+    },
+    Mode {
+        id: 0x0b,
+        endpoint_bits: 12,
+        delta_bits: Some(8),
+    },
+    Mode {
+        id: 0x0f,
+        endpoint_bits: 16,
+        delta_bits: Some(4),
+    },
+];
+
+/// Encode an image for `DXGI_FORMAT_BC6H_UF16`, negative values are clamped to 0
+pub fn encode_unsigned(img: &DynamicImage) -> Vec<Bc6hBlock> {
+    encode(img, false)
+}
+
+/// Encode an image for `DXGI_FORMAT_BC6H_SF16`
+pub fn encode_signed(img: &DynamicImage) -> Vec<Bc6hBlock> {
+    encode(img, true)
+}
+
+fn encode(img: &DynamicImage, signed: bool) -> Vec<Bc6hBlock> {
+    let img = img.to_rgb32f();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        let texels = block.map(|p| p.0.map(|c| to_unquantized(c, signed)));
+        gen_block(&texels, signed)
+    })
+}
+
+/// Decode blocks generated by [`encode_unsigned`] into a float image
+pub fn decode_unsigned(blocks: &[Bc6hBlock], width: u32, height: u32) -> DynamicImage {
+    decode(blocks, width, height, false)
+}
+
+/// Decode blocks generated by [`encode_signed`] into a float image
+pub fn decode_signed(blocks: &[Bc6hBlock], width: u32, height: u32) -> DynamicImage {
+    decode(blocks, width, height, true)
+}
+
+fn decode(blocks: &[Bc6hBlock], width: u32, height: u32, signed: bool) -> DynamicImage {
+    let mut img = Rgb32FImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_block(block, signed).map(Rgb);
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageRgb32F(img)
+}
+
+/// Decode a single block into its 16 texels
+///
+/// Blocks using one of the two region modes decode to black.
+fn decode_block(block: &Bc6hBlock, signed: bool) -> [[f32; 3]; 16] {
+    let mut bits = BitReader::new(block.data);
+    let mut id = bits.read(2);
+    if id >= 2 {
+        id |= bits.read(3) << 2;
+    }
+    let Some(mode) = MODES.iter().find(|mode| mode.id == id) else {
+        return [[0.0; 3]; 16];
+    };
+
+    let mut a = [0i32; 3];
+    let mut b = [0i32; 3];
+    for c in a.iter_mut() {
+        *c = bits.read(10) as i32;
+    }
+    for c in 0..3 {
+        match mode.delta_bits {
+            Some(delta_bits) => {
+                b[c] = bits.read(delta_bits) as i32;
+                // the high bits of the first endpoint are stored in reverse order
+                for bit in (10..mode.endpoint_bits).rev() {
+                    a[c] |= (bits.read(1) as i32) << bit;
+                }
+            }
+            None => b[c] = bits.read(10) as i32,
+        }
+    }
+
+    let bits_a = mode.endpoint_bits;
+    for c in 0..3 {
+        if signed {
+            a[c] = sign_extend(a[c], bits_a);
+        }
+        if let Some(delta_bits) = mode.delta_bits {
+            b[c] = (a[c] + sign_extend(b[c], delta_bits)) & ((1 << bits_a) - 1);
+        }
+        if signed {
+            b[c] = sign_extend(b[c], bits_a);
+        }
+    }
+
+    let a = a.map(|c| unquantize(c, bits_a, signed));
+    let b = b.map(|c| unquantize(c, bits_a, signed));
+    std::array::from_fn(|px| {
+        let idx = bits.read(if px == 0 { 3 } else { 4 }) as usize;
+        let w = WEIGHTS4[idx];
+        std::array::from_fn(|c| {
+            let value = (a[c] * (64 - w) + b[c] * w + 32) >> 6;
+            f16::from_bits(finish_unquantize(value, signed)).to_f32()
+        })
+    })
+}
+
+/// Generate block
+///
+/// * `texels`: Texels of the block converted with [`to_unquantized`]
+/// * `signed`: Whether the block is for a signed format
+fn gen_block(texels: &[[f32; 3]; 16], signed: bool) -> Bc6hBlock {
+    let endpoints = endpoints(texels);
+    MODES
+        .iter()
+        .filter_map(|mode| encode_mode(mode, texels, endpoints, signed))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(block, _)| block)
+        .unwrap()
+}
+
+/// Pick endpoints for a block from the corners of its bounding box
+///
+/// Channels that decrease while the widest channel increases get their endpoints swapped, so the
+/// line between the endpoints follows the colors of the block.
+fn endpoints(texels: &[[f32; 3]; 16]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    let mut mean = [0.0; 3];
+    for texel in texels {
+        for c in 0..3 {
+            min[c] = min[c].min(texel[c]);
+            max[c] = max[c].max(texel[c]);
+            mean[c] += texel[c] / 16.0;
+        }
+    }
+
+    let axis = (0..3)
+        .max_by(|a, b| (max[*a] - min[*a]).total_cmp(&(max[*b] - min[*b])))
+        .unwrap();
+    for c in 0..3 {
+        let covariance: f32 = texels
+            .iter()
+            .map(|t| (t[axis] - mean[axis]) * (t[c] - mean[c]))
+            .sum();
+        if covariance < 0.0 {
+            std::mem::swap(&mut min[c], &mut max[c]);
+        }
+    }
+    (min, max)
+}
+
+/// Encode a block with a given mode
+///
+/// returns the block and its squared error, or `None` if the endpoints can't be ordered the way
+/// the mode requires
+fn encode_mode(
+    mode: &Mode,
+    texels: &[[f32; 3]; 16],
+    endpoints: ([f32; 3], [f32; 3]),
+    signed: bool,
+) -> Option<(Bc6hBlock, f32)> {
+    let (mut start, mut end) = endpoints;
+    // the first texel's index has an implicit high bit of 0, so flip the endpoints if it's closer
+    // to the end of the line
+    for attempt in 0..2 {
+        let (a, b) = quantize_endpoints(mode, start, end, signed);
+        let ua = a.map(|c| unquantize(c, mode.endpoint_bits, signed));
+        let ub = b.map(|c| unquantize(c, mode.endpoint_bits, signed));
+        let palette: [[f32; 3]; 16] = std::array::from_fn(|i| {
+            let w = WEIGHTS4[i];
+            std::array::from_fn(|c| ((ua[c] * (64 - w) + ub[c] * w + 32) >> 6) as f32)
+        });
+
+        let mut indices = [0usize; 16];
+        let mut error = 0.0;
+        for (idx, texel) in indices.iter_mut().zip(texels) {
+            let (i, err) = palette
+                .iter()
+                .map(|p| (0..3).map(|c| (p[c] - texel[c]).powi(2)).sum::<f32>())
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            *idx = i;
+            error += err;
+        }
+
+        if indices[0] >= 8 {
+            if attempt == 0 {
+                std::mem::swap(&mut start, &mut end);
+                continue;
+            }
+            return None;
+        }
+        return Some((write_block(mode, a, b, &indices), error));
+    }
+    None
+}
+
+/// Quantize both endpoints, clamping the second one to what the mode can store
+fn quantize_endpoints(
+    mode: &Mode,
+    start: [f32; 3],
+    end: [f32; 3],
+    signed: bool,
+) -> ([i32; 3], [i32; 3]) {
+    let a = start.map(|c| quantize(c, mode.endpoint_bits, signed));
+    let mut b = end.map(|c| quantize(c, mode.endpoint_bits, signed));
+    if let Some(delta_bits) = mode.delta_bits {
+        let range = 1 << (delta_bits - 1);
+        for c in 0..3 {
+            b[c] = a[c] + (b[c] - a[c]).clamp(-range, range - 1);
+        }
+    }
+    (a, b)
+}
+
+fn write_block(mode: &Mode, a: [i32; 3], b: [i32; 3], indices: &[usize; 16]) -> Bc6hBlock {
+    let mut bits = BitWriter::new();
+    bits.write(mode.id, 5);
+    for c in a {
+        bits.write(c as u32, 10);
+    }
+    for c in 0..3 {
+        match mode.delta_bits {
+            Some(delta_bits) => {
+                bits.write((b[c] - a[c]) as u32, delta_bits);
+                for bit in (10..mode.endpoint_bits).rev() {
+                    bits.write((a[c] >> bit) as u32, 1);
+                }
+            }
+            None => bits.write(b[c] as u32, 10),
+        }
+    }
+    for (px, idx) in indices.iter().enumerate() {
+        bits.write(*idx as u32, if px == 0 { 3 } else { 4 });
+    }
+    Bc6hBlock {
+        data: bits.finish(),
+    }
+}
+
+/// Convert a texel value into the 16 bit space endpoints are interpolated in
+///
+/// Bc6h interpolates the bit patterns of half floats, scaled up by 64/31 for unsigned formats and
+/// by 32/31 for signed formats.
+fn to_unquantized(value: f32, signed: bool) -> f32 {
+    let bits = f16::from_f32(value).to_bits();
+    let magnitude = (bits & 0x7FFF).min(0x7BFF) as f32;
+    let negative = bits & 0x8000 != 0;
+    match (signed, negative) {
+        (false, true) => 0.0,
+        (false, false) => magnitude * 64.0 / 31.0,
+        (true, true) => -magnitude * 32.0 / 31.0,
+        (true, false) => magnitude * 32.0 / 31.0,
+    }
+}
+
+/// Find the quantized endpoint that unquantizes closest to `value`
+fn quantize(value: f32, bits: u32, signed: bool) -> i32 {
+    let (min, max, scale) = if signed {
+        let max = (1 << (bits - 1)) - 1;
+        (-max, max, max as f32 / 32767.0)
+    } else {
+        let max = (1 << bits) - 1;
+        (0, max, max as f32 / 65535.0)
+    };
+    let guess = ((value * scale).round() as i32).clamp(min, max);
+    ((guess - 1).max(min)..=(guess + 1).min(max))
+        .min_by(|a, b| {
+            let error = |q: i32| (unquantize(q, bits, signed) as f32 - value).abs();
+            error(*a).total_cmp(&error(*b))
+        })
+        .unwrap()
+}
+
+/// Expand a quantized endpoint to 16 bits
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if signed {
+        if bits >= 16 {
+            return value;
+        }
+        let magnitude = value.abs();
+        let unq = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 {
+            -unq
+        } else {
+            unq
+        }
+    } else if bits >= 15 {
+        value
+    } else if value == 0 {
+        0
+    } else if value == (1 << bits) - 1 {
+        0xFFFF
+    } else {
+        ((value << 16) + 0x8000) >> bits
+    }
+}
+
+/// Scale an interpolated value back down to the bits of a half float
+fn finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
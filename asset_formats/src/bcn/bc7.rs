@@ -1,220 +1,733 @@
-use crate::bcn::util;
-use bilge::prelude::*;
+//! Bc7 compression of rgb and rgba images
+//!
+//! Every block is encoded with each of the eight block modes, trying every partition, rotation and
+//! index selection the mode allows, and the encoding with the lowest squared error is kept.
+//! [`Quality::Fast`] only tries the first 16 partitions of each mode.
+//!
+//! Errors are measured on linear values. The endpoints of sRGB images are still fitted to the
+//! stored values, since those are what gets interpolated when a block is decoded.
+
+use crate::bcn::util::{
+    channel_tables, least_squares_endpoints, map_blocks, principal_endpoints, put_block,
+    texel_error, BitReader, BitWriter, ChannelTables, WEIGHTS2, WEIGHTS3, WEIGHTS4,
+};
+use crate::bcn::Quality;
+use crate::color::ColorSpace;
 use bytemuck::{Pod, Zeroable};
-use image::imageops::FilterType;
-use image::{DynamicImage, EncodableLayout};
-use log::info;
-use std::io::Read;
+use glam::Vec4;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::ops::Range;
 
-mod palette;
 mod partitions;
-pub use palette::*;
 pub use partitions::*;
 
-/// TODO:
-/// We will want to eventually generalize block mode's well enough that we can just pass in 16
-/// colors to a block and it will generate its self and be able to query it's average error
-/// This way we can just create a block for each mode and see which one is best.
+/// Bc7 Compression block
 ///
-/// We will probably also want to generalize the partitions for the same reason. a partition will
-/// additionally need to know the palette size to score itself. The partition is also what will be
-/// generating the indices (but potentially not encoding them)
-#[bitsize(128)]
-#[derive(Copy, Clone, Pod, Zeroable, DebugBits)]
+/// The mode of the block is the number of zero bits before the first set bit.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
-pub struct Bc7Mode3Block {
-    id: u4,
-    partition: u6,
-    red: [u7; 4],
-    green: [u7; 4],
-    blue: [u7; 4],
-    p: [u1; 4],
-    idx: u30,
-}
-
-pub fn encode(img: &DynamicImage) -> Vec<Bc7Mode3Block> {
-    let w = (img.width() / 4) * 4;
-    let h = (img.height() / 4) * 4;
-    let img = img.resize_exact(w, h, FilterType::Nearest);
-    let mut blocks: Vec<Bc7Mode3Block> = vec![];
-    for y in (0..h).step_by(4) {
-        for x in (0..w).step_by((4)) {
-            // grab cutout of this block
-            let block = img.crop_imm(x, y, 4, 4).to_rgb8();
-            let block = block.as_bytes();
-
-            let pixels: Vec<Color> = block
-                .chunks(3)
-                .map(|b| Color::new(b[0] as f32, b[1] as f32, b[2] as f32, 255.) / 255.)
-                .collect();
-
-            let mut partition = 0;
-            let mut pal = gen_partition_palette(&PARTITIONS[partition], &pixels, 4);
-            let mut map = map_partition(&PARTITIONS[partition], &pixels, &pal);
-            for i in 1..PARTITIONS.len() {
-                let new_pal = gen_partition_palette(&PARTITIONS[i], &pixels, 4);
-                let new_map = map_partition(&PARTITIONS[i], &pixels, &new_pal);
-                if new_map.1 < map.1 {
-                    partition = i;
-                    pal = new_pal;
-                    map = new_map;
-                }
+pub struct Bc7Block {
+    data: [u8; 16],
+}
+
+/// How the p-bits of a mode are stored
+///
+/// A p-bit is shared lowest bit of every channel of an endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PBits {
+    None,
+    /// Each endpoint has its own p-bit
+    Endpoint,
+    /// Both endpoints of a subset share a p-bit
+    Shared,
+}
+
+/// Layout of a block mode
+struct Mode {
+    /// Number of subsets the block is split into
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    /// Precision of the rgb channels of each endpoint, without the p-bit
+    color_bits: u32,
+    /// Precision of the alpha channel of each endpoint, 0 if the block is opaque
+    alpha_bits: u32,
+    pbits: PBits,
+    /// Precision of the primary indices
+    index_bits: u32,
+    /// Precision of the secondary indices, 0 if colors and alpha share the primary indices
+    secondary_index_bits: u32,
+}
+
+const MODES: [Mode; 8] = [
+    Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        pbits: PBits::Endpoint,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        pbits: PBits::Shared,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        pbits: PBits::None,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        pbits: PBits::Endpoint,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        pbits: PBits::None,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        pbits: PBits::None,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        pbits: PBits::Endpoint,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        pbits: PBits::Endpoint,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+/// Encode an opaque image, the alpha channel is ignored
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode(img: &DynamicImage, color_space: ColorSpace, quality: Quality) -> Vec<Bc7Block> {
+    encode_blocks(img, false, color_space, quality)
+}
+
+/// Encode an image with an alpha channel
+///
+/// Blocks that aren't fully opaque only use modes 4 to 7, which store alpha. Modes 4 and 5 fit
+/// alpha with its own set of indices, modes 6 and 7 interpolate it along with the color.
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode_alpha(
+    img: &DynamicImage,
+    color_space: ColorSpace,
+    quality: Quality,
+) -> Vec<Bc7Block> {
+    encode_blocks(img, true, color_space, quality)
+}
+
+fn encode_blocks(
+    img: &DynamicImage,
+    alpha: bool,
+    color_space: ColorSpace,
+    quality: Quality,
+) -> Vec<Bc7Block> {
+    let tables = channel_tables(color_space);
+    let img = img.to_rgba8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        let mut texels = block.map(|p| p.0);
+        if !alpha {
+            texels.iter_mut().for_each(|t| t[3] = 255);
+        }
+        let modes = if texels.iter().all(|t| t[3] == 255) {
+            0..MODES.len()
+        } else {
+            4..MODES.len()
+        };
+        gen_block(&texels, modes, quality, &tables)
+    })
+}
+
+/// Decode a list of Bc7 blocks back into an rgba image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode(blocks: &[Bc7Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_block(block).map(Rgba);
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Decode a single block into its 16 texels
+///
+/// Blocks with the reserved mode decode to transparent black.
+fn decode_block(block: &Bc7Block) -> [[u8; 4]; 16] {
+    let id = block.data[0].trailing_zeros() as usize;
+    let Some(mode) = MODES.get(id) else {
+        return [[0; 4]; 16];
+    };
+    let mut bits = BitReader::new(block.data);
+    bits.read(id as u32 + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits) as usize;
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // endpoints are stored as all reds, then all greens, then all blues and finally all alphas
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    for c in 0..4 {
+        let channel_bits = if c < 3 {
+            mode.color_bits
+        } else {
+            mode.alpha_bits
+        };
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[c] = bits.read(channel_bits);
             }
-            let a_pal = pal[0].raw();
-            let b_pal = pal[1].raw();
-
-            let mut indices = vec![];
-            // map each color
-            for y in 0..4 {
-                for p in 0..2 {
-                    for x in p * 2..(p * 2) + 2 {
-                        let idx = ((y * 4) + x) * 3;
-                        let col = [block[idx], block[idx + 1], block[idx + 2]];
-                        let pal = if p == 0 { a_pal } else { b_pal };
-                        let mapped = index(col, pal.0, pal.1);
-                        indices.push(mapped);
-                    }
+        }
+    }
+    let mut pbits = [[None; 2]; 3];
+    for subset in pbits.iter_mut().take(mode.subsets) {
+        match mode.pbits {
+            PBits::None => {}
+            PBits::Endpoint => *subset = [Some(bits.read(1)), Some(bits.read(1))],
+            PBits::Shared => *subset = [Some(bits.read(1)); 2],
+        }
+    }
+    let colors: [[[u8; 4]; 2]; 3] = std::array::from_fn(|subset| {
+        std::array::from_fn(|e| {
+            std::array::from_fn(|c| {
+                let channel_bits = if c < 3 {
+                    mode.color_bits
+                } else {
+                    mode.alpha_bits
+                };
+                if channel_bits == 0 {
+                    255
+                } else {
+                    expand(endpoints[subset][e][c], channel_bits, pbits[subset][e])
                 }
-            }
+            })
+        })
+    });
+
+    // the anchor index of each subset has an implicit high bit of 0
+    let (subsets, anchors) = partition_table(mode.subsets, partition);
+    let primary: [u32; 16] = std::array::from_fn(|px| {
+        let anchor = anchors[subsets[px] as usize] == px;
+        bits.read(mode.index_bits - anchor as u32)
+    });
+    let secondary: [u32; 16] = std::array::from_fn(|px| match mode.secondary_index_bits {
+        0 => 0,
+        index_bits => bits.read(index_bits - (px == 0) as u32),
+    });
 
-            // now we have a full array of indices
-            // we need to try to map them to a 30 bit area
-            let mut data = 0u32;
-            for (i, idx) in indices.iter().enumerate() {
-                let idx = *idx as u32 & 0x3; // only first 2 bits
-                let idx = idx << (i * 2);
-                data = data | idx;
+    std::array::from_fn(|px| {
+        let [a, b] = colors[subsets[px] as usize];
+        let (color, alpha) = match (mode.secondary_index_bits, index_selection) {
+            (0, _) => (
+                (primary[px], mode.index_bits),
+                (primary[px], mode.index_bits),
+            ),
+            (secondary_bits, 0) => (
+                (primary[px], mode.index_bits),
+                (secondary[px], secondary_bits),
+            ),
+            (secondary_bits, _) => (
+                (secondary[px], secondary_bits),
+                (primary[px], mode.index_bits),
+            ),
+        };
+        let texel = std::array::from_fn(|c| {
+            let (idx, index_bits) = if c < 3 { color } else { alpha };
+            interpolate(a[c], b[c], weights(index_bits)[idx as usize])
+        });
+        rotate(texel, rotation)
+    })
+}
+
+/// Generate the block with the lowest error out of a range of modes
+///
+/// * `texels`: Rgba texels of the block in row-major order
+/// * `modes`: Modes to try
+/// * `tables`: Linear value of each channel of the texels
+fn gen_block(
+    texels: &[[u8; 4]; 16],
+    modes: Range<usize>,
+    quality: Quality,
+    tables: &ChannelTables,
+) -> Bc7Block {
+    modes
+        .map(|id| gen_mode_block(id, texels, quality, tables))
+        .min_by_key(|(_, error)| *error)
+        .unwrap()
+        .0
+}
+
+/// Generate the best block for a single mode
+///
+/// Only [`Quality::Slow`] refines the endpoints of every partition, rotation and index selection
+/// that is tried, [`Quality::Normal`] refines the best one once the search is done.
+///
+/// returns the block and its total squared error
+fn gen_mode_block(
+    id: usize,
+    texels: &[[u8; 4]; 16],
+    quality: Quality,
+    tables: &ChannelTables,
+) -> (Bc7Block, u32) {
+    let mode = &MODES[id];
+    let partitions = match quality {
+        Quality::Fast => (1 << mode.partition_bits).min(16),
+        _ => 1 << mode.partition_bits,
+    };
+    let refinements = match quality {
+        Quality::Slow => quality.refinements(),
+        _ => 0,
+    };
+    let mut best: Option<([u32; 3], Bc7Block, u32)> = None;
+    for partition in 0..partitions {
+        for rotation in 0..1 << mode.rotation_bits {
+            for index_selection in 0..1 << mode.index_selection_bits {
+                let header = [partition, rotation, index_selection];
+                let (block, error) = fit_block(id, header, texels, refinements, tables);
+                if best.is_some_and(|(_, _, best)| best <= error) {
+                    continue;
+                }
+                best = Some((header, block, error));
             }
-            blocks.push(Bc7Mode3Block::new(
-                u4::new(0b1000),
-                u6::extract_u8(partition as u8, 0),
-                [
-                    u7::extract_u8(a_pal.0[0], 1),
-                    u7::extract_u8(a_pal.1[0], 1),
-                    u7::extract_u8(b_pal.0[0], 1),
-                    u7::extract_u8(b_pal.1[0], 1),
-                ],
-                [
-                    u7::extract_u8(a_pal.0[1], 1),
-                    u7::extract_u8(a_pal.1[1], 1),
-                    u7::extract_u8(b_pal.0[1], 1),
-                    u7::extract_u8(b_pal.1[1], 1),
-                ],
-                [
-                    u7::extract_u8(a_pal.0[2], 1),
-                    u7::extract_u8(a_pal.1[2], 1),
-                    u7::extract_u8(b_pal.0[2], 1),
-                    u7::extract_u8(b_pal.1[2], 1),
-                ],
-                [u1::new(0); 4],
-                //u30::new(0x1D834000),
-                u30::extract_u32(data, 0),
-            ))
         }
     }
-    blocks
+    let (header, block, error) = best.unwrap();
+    if quality != Quality::Normal {
+        return (block, error);
+    }
+    let refined = fit_block(id, header, texels, quality.refinements(), tables);
+    if refined.1 < error {
+        refined
+    } else {
+        (block, error)
+    }
 }
 
-// thoughts on actually encoding this shit.
-// we'll need to generate
-fn gen_pal(colors: &[u8]) -> ([u8; 3], [u8; 3]) {
-    /*
-    // generate with min/max
-    let mut min = [255u8;3];
-    let mut max = [0u8;3];
-    for pixel in colors.chunks(3) {
-        for i in 0..3 {
-            if pixel[i] < min[i] {
-                min[i] = pixel[i];
+/// Fit a block for a single partition, rotation and index selection of a mode
+///
+/// * `header`: Partition, rotation and index selection of the block
+/// * `refinements`: Least squares refinement rounds for the endpoints of each subset
+///
+/// returns the block and its total squared error
+fn fit_block(
+    id: usize,
+    header: [u32; 3],
+    texels: &[[u8; 4]; 16],
+    refinements: usize,
+    tables: &ChannelTables,
+) -> (Bc7Block, u32) {
+    let mode = &MODES[id];
+    let [partition, rotation, index_selection] = header.map(|field| field as usize);
+    let (subsets, anchors) = partition_table(mode.subsets, partition);
+    let rotated = texels.map(|t| rotate(t, rotation));
+    let rotated_tables = rotate(*tables, rotation);
+    let (color_index_bits, alpha_index_bits) = match index_selection {
+        0 => (mode.index_bits, mode.secondary_index_bits),
+        _ => (mode.secondary_index_bits, mode.index_bits),
+    };
+    // modes with secondary indices fit the alpha channel on its own
+    let channels = if mode.alpha_bits == 0 || mode.secondary_index_bits > 0 {
+        0..3
+    } else {
+        0..4
+    };
+    let fits: Vec<Fit> = (0..mode.subsets)
+        .map(|subset| {
+            let members = subsets.map(|s| s as usize == subset);
+            Fit::new(&rotated, &rotated_tables, members, anchors[subset]).channels(
+                channels.clone(),
+                mode.color_bits,
+                mode.pbits,
+                color_index_bits,
+                refinements,
+            )
+        })
+        .collect();
+    let alpha = (mode.secondary_index_bits > 0).then(|| {
+        Fit::new(&rotated, &rotated_tables, [true; 16], 0).channels(
+            3..4,
+            mode.alpha_bits,
+            mode.pbits,
+            alpha_index_bits,
+            refinements,
+        )
+    });
+
+    let mut error: u32 = fits.iter().chain(&alpha).map(|fit| fit.error).sum();
+    if mode.alpha_bits == 0 {
+        error += texels
+            .iter()
+            .map(|t| texel_error(t, &[0, 0, 0, 255], 3..4, tables))
+            .sum::<u32>();
+    }
+    let block = write_block(id, header, subsets, &anchors, &fits, alpha.as_ref());
+    (block, error)
+}
+
+/// Pack the fitted subsets of a block into its bit layout
+///
+/// * `id`: Mode of the block
+/// * `header`: Partition, rotation and index selection of the block
+/// * `subsets`: Subset of each texel
+/// * `anchors`: Anchor texel of each subset
+/// * `fits`: Fitted endpoints and indices of each subset
+/// * `alpha`: Separately fitted alpha channel for modes with secondary indices
+fn write_block(
+    id: usize,
+    header: [u32; 3],
+    subsets: &[u8; 16],
+    anchors: &[usize; 3],
+    fits: &[Fit],
+    alpha: Option<&Fit>,
+) -> Bc7Block {
+    let mode = &MODES[id];
+    let mut bits = BitWriter::new();
+    bits.write(1 << id, id as u32 + 1);
+    bits.write(header[0], mode.partition_bits);
+    bits.write(header[1], mode.rotation_bits);
+    bits.write(header[2], mode.index_selection_bits);
+
+    for c in 0..4 {
+        let channel_bits = if c < 3 {
+            mode.color_bits
+        } else {
+            mode.alpha_bits
+        };
+        for fit in fits {
+            let fit = if c == 3 { alpha.unwrap_or(fit) } else { fit };
+            for endpoint in fit.endpoints {
+                bits.write(endpoint[c], channel_bits);
             }
-            if pixel[i] > max[i] {
-                max[i] = pixel[i];
+        }
+    }
+    for fit in fits {
+        match mode.pbits {
+            PBits::None => {}
+            PBits::Endpoint => fit.pbits.iter().for_each(|p| bits.write(*p, 1)),
+            PBits::Shared => bits.write(fit.pbits[0], 1),
+        }
+    }
+
+    match alpha {
+        Some(alpha) => {
+            let (primary, secondary) = match header[2] {
+                0 => (&fits[0], alpha),
+                _ => (alpha, &fits[0]),
+            };
+            for px in 0..16 {
+                bits.write(primary.indices[px], mode.index_bits - (px == 0) as u32);
+            }
+            for px in 0..16 {
+                let index_bits = mode.secondary_index_bits - (px == 0) as u32;
+                bits.write(secondary.indices[px], index_bits);
+            }
+        }
+        None => {
+            for (px, &subset) in subsets.iter().enumerate() {
+                let subset = subset as usize;
+                let anchor = anchors[subset] == px;
+                bits.write(fits[subset].indices[px], mode.index_bits - anchor as u32);
             }
         }
     }
+    Bc7Block {
+        data: bits.finish(),
+    }
+}
 
-    let mut acc =  palette_error(colors, min, max);
-    let mut min2 = min.clone();
-    let mut max2 = max.clone();
-    let mut acc2 = acc;
-    // TODO: Smarter optimization algorithm
-    for c in 0..3 {
-        for i in -200..200 {
-            for j in -200..200 {
-                let min3 = (min[c] as i16 + i) as u8;
-                let max3 = (max[c] as i16 + j) as u8;
-                let mut min = min2.clone();
-                min[c] = min3;
-                let mut max = max2.clone();
-                max[c] = max3;
-                let acc =  palette_error(colors, min, max);
-                if acc < acc2 {
-                    min2 = min;
-                    max2 = max;
-                    acc2 = acc;
-                }
+/// Quantized endpoints and indices for the texels of one subset
+#[derive(Debug, Clone, Copy)]
+struct Fit<'a> {
+    texels: &'a [[u8; 4]; 16],
+    tables: &'a ChannelTables,
+    members: [bool; 16],
+    anchor: usize,
+    /// Endpoints without their p-bits, channels that weren't fitted are left at 0
+    endpoints: [[u32; 4]; 2],
+    pbits: [u32; 2],
+    indices: [u32; 16],
+    /// Total squared error of the fitted channels
+    error: u32,
+}
+
+impl<'a> Fit<'a> {
+    /// * `texels`: Texels of the whole block
+    /// * `tables`: Linear value of each channel of the texels
+    /// * `members`: Which texels belong to the subset
+    /// * `anchor`: Texel whose index has to fit in one bit less than the others
+    fn new(
+        texels: &'a [[u8; 4]; 16],
+        tables: &'a ChannelTables,
+        members: [bool; 16],
+        anchor: usize,
+    ) -> Self {
+        Self {
+            texels,
+            tables,
+            members,
+            anchor,
+            endpoints: [[0; 4]; 2],
+            pbits: [0; 2],
+            indices: [0; 16],
+            error: 0,
+        }
+    }
+
+    /// Fit endpoints and indices for some channels of the subset
+    ///
+    /// * `channels`: Channels to fit
+    /// * `bits`: Precision of each endpoint channel without the p-bit
+    /// * `pbits`: How p-bits are stored
+    /// * `index_bits`: Precision of the indices
+    /// * `refinements`: Least squares refinement rounds for the endpoints
+    fn channels(
+        self,
+        channels: Range<usize>,
+        bits: u32,
+        pbits: PBits,
+        index_bits: u32,
+        refinements: usize,
+    ) -> Self {
+        let endpoints = self.endpoints(channels.clone());
+        let mut best = self.quantize(endpoints, channels.clone(), bits, pbits, index_bits);
+        for _ in 0..refinements {
+            let Some(endpoints) = best.least_squares(channels.clone(), index_bits) else {
+                break;
+            };
+            let refined = self.quantize(endpoints, channels.clone(), bits, pbits, index_bits);
+            if refined.error >= best.error {
+                break;
             }
+            best = refined;
         }
+        best
+    }
+
+    /// Quantize a pair of endpoints with every combination of p-bits and keep the best
+    fn quantize(
+        self,
+        (a, b): ([f32; 4], [f32; 4]),
+        channels: Range<usize>,
+        bits: u32,
+        pbits: PBits,
+        index_bits: u32,
+    ) -> Self {
+        let combinations: &[[u32; 2]] = match pbits {
+            PBits::None => &[[0, 0]],
+            PBits::Endpoint => &[[0, 0], [0, 1], [1, 0], [1, 1]],
+            PBits::Shared => &[[0, 0], [1, 1]],
+        };
+        combinations
+            .iter()
+            .map(|p| {
+                let p = p.map(|p| (pbits != PBits::None).then_some(p));
+                let mut fit = self;
+                for c in channels.clone() {
+                    fit.endpoints[0][c] = quantize(a[c], bits, p[0]);
+                    fit.endpoints[1][c] = quantize(b[c], bits, p[1]);
+                }
+                fit.pbits = p.map(|p| p.unwrap_or(0));
+                fit.map(channels.clone(), bits, p, index_bits)
+            })
+            .min_by_key(|fit| fit.error)
+            .unwrap()
     }
 
-    // check accuracy
-    info!("Pallete average error: {} reduced from {}", acc2, acc);*/
+    /// Unquantized endpoints along the principal axis of the subset's texels
+    fn endpoints(&self, channels: Range<usize>) -> ([f32; 4], [f32; 4]) {
+        let points: Vec<Vec4> = (0..16)
+            .filter(|px| self.members[*px])
+            .map(|px| to_point(self.texels[px], channels.clone()))
+            .collect();
+        let (a, b) = principal_endpoints(&points);
+        (a.to_array(), b.to_array())
+    }
 
-    // maybe try out fancy new linreg algorithm
-    let (min, max) = util::generate_palette3d(&colors);
-    //let lin_err = palette_error(colors, min, max);
-    //info!("Linear regression average error: {} change: {},{}", lin_err, lin_err - acc, lin_err-acc2);
+    /// Unquantized endpoints that reproduce the texels best for their current indices
+    fn least_squares(
+        &self,
+        channels: Range<usize>,
+        index_bits: u32,
+    ) -> Option<([f32; 4], [f32; 4])> {
+        let weights = weights(index_bits);
+        let points = (0..16).filter(|px| self.members[*px]).map(|px| {
+            let weight = weights[self.indices[px] as usize] as f32 / 64.0;
+            (to_point(self.texels[px], channels.clone()), weight)
+        });
+        let (a, b) = least_squares_endpoints(points)?;
+        Some((a.to_array(), b.to_array()))
+    }
 
-    (min, max)
-}
+    /// Map each texel of the subset to its closest palette entry
+    ///
+    /// The endpoints are swapped if needed so that the anchor index fits in its shortened field.
+    fn map(
+        mut self,
+        channels: Range<usize>,
+        bits: u32,
+        pbits: [Option<u32>; 2],
+        index_bits: u32,
+    ) -> Self {
+        let colors = [0, 1].map(|e| self.endpoints[e].map(|q| expand(q, bits, pbits[e])));
+        let palette: Vec<[u8; 4]> = weights(index_bits)
+            .iter()
+            .map(|w| std::array::from_fn(|c| interpolate(colors[0][c], colors[1][c], *w)))
+            .collect();
+
+        self.error = 0;
+        for px in (0..16).filter(|px| self.members[*px]) {
+            let texel = self.texels[px];
+            let (idx, error) = palette
+                .iter()
+                .map(|p| texel_error(p, &texel, channels.clone(), self.tables))
+                .enumerate()
+                .min_by_key(|(_, error)| *error)
+                .unwrap();
+            self.indices[px] = idx as u32;
+            self.error += error;
+        }
 
-fn palette_error(colors: &[u8], a: [u8; 3], b: [u8; 3]) -> i16 {
-    let palette = palette(a, b);
-    let mut error = 0;
-    for color in colors.chunks(3) {
-        let color = [color[0], color[1], color[2]];
-        let pal = palette[index(color, a, b) as usize];
-        error += cmp_color(color, pal);
+        let max_index = (1 << index_bits) - 1;
+        if self.indices[self.anchor] > max_index / 2 {
+            self.endpoints.swap(0, 1);
+            self.pbits.swap(0, 1);
+            for px in (0..16).filter(|px| self.members[*px]) {
+                self.indices[px] = max_index - self.indices[px];
+            }
+        }
+        self
     }
-    error / 16
 }
 
-fn palette(a: [u8; 3], b: [u8; 3]) -> [[u8; 3]; 4] {
-    let mut palette = [[0u8; 3]; 4];
-    for i in 0..4 {
-        for c in 0..3 {
-            palette[i][c] = pol(a[c], b[c], i as u8, 5);
+/// Some channels of a texel as a point to fit endpoints to, the other channels are 0
+fn to_point(texel: [u8; 4], channels: Range<usize>) -> Vec4 {
+    Vec4::from_array(std::array::from_fn(|c| {
+        if channels.contains(&c) {
+            texel[c] as f32
+        } else {
+            0.0
         }
+    }))
+}
+
+/// Subset of each texel and the anchor texel of each subset for a partition
+fn partition_table(subsets: usize, partition: usize) -> (&'static [u8; 16], [usize; 3]) {
+    match subsets {
+        1 => (&[0; 16], [0; 3]),
+        2 => (
+            &PARTITIONS[partition],
+            [0, SECOND_SUBSET_ANCHORS[partition], 0],
+        ),
+        _ => (
+            &PARTITIONS3[partition],
+            [
+                0,
+                THREE_SUBSET_SECOND_ANCHORS[partition],
+                THREE_SUBSET_THIRD_ANCHORS[partition],
+            ],
+        ),
     }
-    palette
 }
 
-fn index(color: [u8; 3], a: [u8; 3], b: [u8; 3]) -> u8 {
-    let palette = palette(a, b);
-    let mut pal = 0;
-    for (i, p) in palette.iter().enumerate() {
-        if cmp_color(*p, color) < cmp_color(palette[pal as usize], color) {
-            pal = i as u8;
-        }
+/// Swap the alpha channel with one of the color channels, rotation 0 leaves the texel untouched
+fn rotate<T>(mut texel: [T; 4], rotation: usize) -> [T; 4] {
+    if rotation > 0 {
+        texel.swap(rotation - 1, 3);
     }
-    pal
+    texel
 }
 
-fn cmp_color(a: [u8; 3], b: [u8; 3]) -> i16 {
-    // convert to signed values
-    let a = [a[0] as f32, a[1] as f32, a[2] as f32];
-    let b = [b[0] as f32, b[1] as f32, b[2] as f32];
+fn weights(index_bits: u32) -> &'static [i32] {
+    match index_bits {
+        2 => &WEIGHTS2,
+        3 => &WEIGHTS3,
+        _ => &WEIGHTS4,
+    }
+}
+
+fn interpolate(a: u8, b: u8, weight: i32) -> u8 {
+    (((64 - weight) * a as i32 + weight * b as i32 + 32) >> 6) as u8
+}
 
-    let diff = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
-    let m = (diff[0] * diff[0]) + (diff[1] * diff[1]) + (diff[2] * diff[2]);
-    let m = (m as f32).sqrt() as i16;
-    m
+/// Expand a quantized endpoint channel and its p-bit to 8 bits
+fn expand(value: u32, bits: u32, pbit: Option<u32>) -> u8 {
+    let (value, bits) = match pbit {
+        Some(p) => ((value << 1) | p, bits + 1),
+        None => (value, bits),
+    };
+    ((value << (8 - bits)) | (value >> (2 * bits - 8))) as u8
 }
 
-fn pol(a: u8, b: u8, i: u8, max: u8) -> u8 {
-    let a = a as u16;
-    let b = b as u16;
-    let i = i as u16;
-    let max = max as u16;
-    (((max - i) * a + i * b) / max) as u8
+/// Find the quantized value that expands closest to `value`
+fn quantize(value: f32, bits: u32, pbit: Option<u32>) -> u32 {
+    let total_bits = bits + pbit.is_some() as u32;
+    let guess =
+        (value / 255.0 * ((1 << total_bits) - 1) as f32).round() as i32 >> (total_bits - bits);
+    (guess - 1..=guess + 1)
+        .map(|q| q.clamp(0, (1 << bits) - 1) as u32)
+        .min_by(|a, b| {
+            let error = |q: &u32| (expand(*q, bits, pbit) as f32 - value).abs();
+            error(a).total_cmp(&error(b))
+        })
+        .unwrap()
 }
@@ -0,0 +1,26 @@
+/// How much effort the block encoders spend on each block
+///
+/// Endpoints always start on the principal axis of a block's colors. Higher qualities refine them
+/// with least squares against the palette indices they map to, keeping every round that lowers
+/// the block's squared error.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Principal axis endpoints without refinement, Bc7 only tries the first 16 partitions
+    Fast,
+    /// Refine the endpoints of the best encoding a block mode finds
+    #[default]
+    Normal,
+    /// Refine the endpoints of every encoding that is tried, several times slower for Bc7
+    Slow,
+}
+
+impl Quality {
+    /// Maximum number of least squares refinement rounds for a set of endpoints
+    pub(crate) fn refinements(self) -> usize {
+        match self {
+            Quality::Fast => 0,
+            Quality::Normal => 2,
+            Quality::Slow => 8,
+        }
+    }
+}
@@ -1,125 +1,317 @@
-use hsl::HSL;
-use linreg::linear_regression;
-
-/// Computes the average error of mapping a block of values to a given palette
-///
-/// * `values`: The original dataset
-/// * `pal`: Endpoints of the palette
-/// * `colors`: Number of colors in the palette
-pub fn avg_error(values: &[u8], pal: (u8, u8), colors: usize) -> u8 {
-    let mut error = 0;
-    for value in values {
-        let (i, c) = map_color(*value, pal, colors);
-        error += value.abs_diff(c);
+use crate::color::{linear_table, ColorSpace};
+use glam::{Mat4, Vec4};
+use image::{ImageBuffer, Pixel};
+use rayon::prelude::*;
+use std::ops::Range;
+
+/// Copies a 4x4 block of texels out of an image
+///
+/// Texels past the right and bottom edges repeat the last column and row of the image, so partial
+/// edge blocks are padded with colors that are already in the block.
+///
+/// * `img`: Image to read from
+/// * `x`: Horizontal pixel position of the block's top left texel
+/// * `y`: Vertical pixel position of the block's top left texel
+///
+/// returns the texels of the block in row-major order
+pub fn get_block<P: Pixel>(img: &ImageBuffer<P, Vec<P::Subpixel>>, x: u32, y: u32) -> [P; 16] {
+    std::array::from_fn(|i| {
+        let tx = (x + i as u32 % 4).min(img.width() - 1);
+        let ty = (y + i as u32 / 4).min(img.height() - 1);
+        *img.get_pixel(tx, ty)
+    })
+}
+
+/// Encodes every 4x4 block of an image in parallel
+///
+/// Blocks are spread over the threads of the current rayon pool, run the encoder inside
+/// [`rayon::ThreadPool::install`] to control how many threads are used. Blocks are still returned in
+/// row-major order, so the output is identical to encoding them one at a time.
+///
+/// * `img`: Image to encode
+/// * `encode`: Encodes the texels of a single block, see [`get_block`]
+pub fn map_blocks<P, B>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    encode: impl Fn([P; 16]) -> B + Sync,
+) -> Vec<B>
+where
+    P: Pixel + Sync,
+    P::Subpixel: Sync,
+    B: Send,
+{
+    let blocks_wide = img.width().div_ceil(4);
+    let blocks_high = img.height().div_ceil(4);
+    (0..blocks_wide * blocks_high)
+        .into_par_iter()
+        .map(|i| encode(get_block(img, i % blocks_wide * 4, i / blocks_wide * 4)))
+        .collect()
+}
+
+/// Copies a decoded 4x4 block of texels into an image
+///
+/// Texels that fall outside of the image are dropped, so partial blocks along the right and bottom
+/// edges can be written without special handling.
+///
+/// * `img`: Image to write into
+/// * `x`: Horizontal pixel position of the block's top left texel
+/// * `y`: Vertical pixel position of the block's top left texel
+/// * `texels`: Texels of the block in row-major order
+pub fn put_block<P: Pixel>(
+    img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    x: u32,
+    y: u32,
+    texels: &[P; 16],
+) {
+    for (i, texel) in texels.iter().enumerate() {
+        let (tx, ty) = (x + i as u32 % 4, y + i as u32 / 4);
+        if tx < img.width() && ty < img.height() {
+            img.put_pixel(tx, ty, *texel);
+        }
     }
-    error / values.len() as u8
 }
 
-/// Map a color value to it's closest match in a palette
+/// Encodes every block of an image in parallel, like [`map_blocks`] for blocks of any footprint
 ///
-/// * `color`: The color we are trying to map
-/// * `pal`: The  endpoints of the palette
-/// * `colors`: The number of colors in the palette
+/// * `img`: Image to encode
+/// * `footprint`: Width and height of a block in texels
+/// * `encode`: Encodes the texels of a single block in row-major order, partial blocks are padded
+///   like [`get_block`]
+pub fn map_footprint<P, B>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    footprint: (u32, u32),
+    encode: impl Fn(&[P]) -> B + Sync,
+) -> Vec<B>
+where
+    P: Pixel + Sync,
+    P::Subpixel: Sync,
+    B: Send,
+{
+    let (block_width, block_height) = footprint;
+    let blocks_wide = img.width().div_ceil(block_width);
+    let blocks_high = img.height().div_ceil(block_height);
+    (0..blocks_wide * blocks_high)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (
+                i % blocks_wide * block_width,
+                i / blocks_wide * block_height,
+            );
+            let texels: Vec<P> = (0..block_width * block_height)
+                .map(|t| {
+                    let tx = (x + t % block_width).min(img.width() - 1);
+                    let ty = (y + t / block_width).min(img.height() - 1);
+                    *img.get_pixel(tx, ty)
+                })
+                .collect();
+            encode(&texels)
+        })
+        .collect()
+}
+
+/// Copies a decoded block of any footprint into an image, like [`put_block`]
 ///
-/// # Returns
-///  A tuple representing `(index, value)`
-pub fn map_color(color: u8, pal: (u8, u8), colors: usize) -> (usize, u8) {
-    let min = pal.0.min(pal.1);
-    let max = pal.0.max(pal.1);
-
-    let mut closest = (0, min);
-    let step = (max - min) as usize / colors;
-    for idx in 0..colors {
-        let c = min + (step * idx) as u8;
-        if color.abs_diff(c) < color.abs_diff(closest.1) {
-            closest = (idx, c);
+/// * `footprint`: Width and height of the block in texels
+/// * `texels`: Texels of the block in row-major order
+pub fn put_footprint<P: Pixel>(
+    img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    x: u32,
+    y: u32,
+    footprint: (u32, u32),
+    texels: &[P],
+) {
+    for (i, texel) in texels.iter().enumerate() {
+        let (tx, ty) = (x + i as u32 % footprint.0, y + i as u32 / footprint.0);
+        if tx < img.width() && ty < img.height() {
+            img.put_pixel(tx, ty, *texel);
         }
     }
-    closest
 }
 
-/// Generates endpoints for a set of values
+/// Reads little-endian bit fields out of a 128 bit compressed block, starting at the lowest bit
+pub struct BitReader {
+    bits: u128,
+}
+
+impl BitReader {
+    pub fn new(bytes: [u8; 16]) -> Self {
+        Self {
+            bits: u128::from_le_bytes(bytes),
+        }
+    }
+
+    /// Read the next `count` bits of the block
+    pub fn read(&mut self, count: u32) -> u32 {
+        debug_assert!(count <= 32);
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        value
+    }
+}
+
+/// Writes little-endian bit fields into a 128 bit compressed block, starting at the lowest bit
+#[derive(Default)]
+pub struct BitWriter {
+    bits: u128,
+    offset: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the lowest `count` bits of `value` after the previously written bits
+    pub fn write(&mut self, value: u32, count: u32) {
+        debug_assert!(count <= 32 && self.offset + count <= 128);
+        let mask = (1u128 << count) - 1;
+        self.bits |= (value as u128 & mask) << self.offset;
+        self.offset += count;
+    }
+
+    pub fn finish(self) -> [u8; 16] {
+        self.bits.to_le_bytes()
+    }
+}
+
+/// Lookup tables turning each channel of an 8 bit texel into a linear value
+pub type ChannelTables = [&'static [f32; 256]; 4];
+
+/// Tables for rgba texels with their color channels stored in `color_space`
+pub fn channel_tables(color_space: ColorSpace) -> ChannelTables {
+    let color = linear_table(color_space);
+    [color, color, color, linear_table(ColorSpace::Linear)]
+}
+
+/// Squared error between some channels of two texels, compared as linear values
 ///
-/// * `values`: List of values that need to be represented
-/// * `colors`: Number of color clusters to base the endpoints on
-pub fn generate_palette<T: PartialOrd + PartialEq + Into<f32> + Clone>(values: &[T]) -> (f32, f32) {
-    let mut values = values.to_vec();
-    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    values.dedup();
-
-    // special case if n = 1;
-    if values.len() == 1 {
-        return (values[0].clone().into(), values[0].clone().into());
+/// The error is measured in 16ths of an 8 bit step, so small differences between dark sRGB
+/// values aren't rounded away.
+pub fn texel_error(
+    a: &[u8; 4],
+    b: &[u8; 4],
+    channels: Range<usize>,
+    tables: &ChannelTables,
+) -> u32 {
+    channels
+        .map(|c| {
+            let diff = tables[c][a[c] as usize] - tables[c][b[c] as usize];
+            (diff * 16.0).powi(2).round() as u32
+        })
+        .sum()
+}
+
+/// Interpolation weights for 2 bit indices
+pub const WEIGHTS2: [i32; 4] = [0, 21, 43, 64];
+
+/// Interpolation weights for 3 bit indices
+pub const WEIGHTS3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+
+/// Interpolation weights for 4 bit indices, shared by Bc6h and Bc7
+pub const WEIGHTS4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Computes the mean squared error of mapping a set of values to a palette
+///
+/// * `values`: The original dataset, an empty dataset has no error
+/// * `palette`: Every value the dataset can be mapped to
+pub fn avg_error(values: &[i32], palette: &[i32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
     }
-    /*if values.len() == 2 {
-        return (values[0].clone().into(), values[1].clone().into());
-    }*/
+    let error: u32 = values.iter().map(|v| map_color(*v, palette).1).sum();
+    error as f32 / values.len() as f32
+}
 
-    let values: Vec<f32> = values.iter().map(|v| v.clone().into()).collect();
+/// Map a value to its closest match in a palette
+///
+/// * `value`: The value we are trying to map
+/// * `palette`: Every value of the palette, it can't be empty
+///
+/// # Returns
+///  A tuple representing `(index, squared error)`
+pub fn map_color(value: i32, palette: &[i32]) -> (usize, u32) {
+    palette
+        .iter()
+        .map(|c| value.abs_diff(*c).pow(2))
+        .enumerate()
+        .min_by_key(|(_, error)| *error)
+        .unwrap()
+}
+
+/// Generates endpoints for a set of values
+///
+/// In a single dimension the principal axis is the value axis itself, so the endpoints are the
+/// smallest and largest value. An empty set of values gets `(0.0, 0.0)` and NaN values are ignored.
+///
+/// * `values`: List of values that need to be represented
+pub fn generate_palette<T: Into<f32> + Clone>(values: &[T]) -> (f32, f32) {
+    values
+        .iter()
+        .map(|v| v.clone().into())
+        .filter(|v: &f32| !v.is_nan())
+        .fold(None, |range, v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+        })
+        .unwrap_or((0.0, 0.0))
+}
 
-    let mut indices = vec![0.0; values.len()];
-    for (i, e) in indices.iter_mut().enumerate() {
-        *e = i as f32;
+/// Endpoints of the line through a set of points along their principal axis
+///
+/// The axis is the eigenvector of the points' covariance matrix with the largest eigenvalue,
+/// found with power iteration. The endpoints are where the outermost points project onto it, so
+/// the line spans every point. Channels that aren't used should be left at 0.
+pub fn principal_endpoints(points: &[Vec4]) -> (Vec4, Vec4) {
+    if points.is_empty() {
+        return (Vec4::ZERO, Vec4::ZERO);
     }
+    let mean = points.iter().sum::<Vec4>() / points.len() as f32;
+    let covariance = points.iter().fold(Mat4::ZERO, |covariance, p| {
+        let d = *p - mean;
+        covariance + Mat4::from_cols(d * d.x, d * d.y, d * d.z, d * d.w)
+    });
 
-    let (m, b): (f32, f32) = linear_regression(&indices, &values).unwrap();
-    let line = |x| m * x as f32 + b;
-    let min = line(0);
-    let max = line(values.len() - 1);
-    (min, max)
-}
-
-/// Thoughts on being able to generate a line for colors:
-/// Ok, so when generating colors it may be smart to drop into a HSL like colorspace
-/// We want to figure out the lightness range, this is going to be very apparent to the user so.
-/// We also don't want to randomize the hue. So we need to figure out the best 2 hues to use.
-/// Then we should be able to create our endpoint colors
-pub use generate_palette3d_rgb as generate_palette3d;
-
-pub fn generate_palette3d_rgb(values: &[u8]) -> ([u8; 3], [u8; 3]) {
-    let values: Vec<[u8; 3]> = values
-        .chunks(3)
-        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
-        .collect();
-    let red: Vec<u8> = values.iter().map(|c| c[0]).collect();
-    let grn: Vec<u8> = values.iter().map(|c| c[1]).collect();
-    let blu: Vec<u8> = values.iter().map(|c| c[2]).collect();
-
-    let red = generate_palette(&red);
-    let grn = generate_palette(&grn);
-    let blu = generate_palette(&blu);
-
-    let min = [red.0 as u8, grn.0 as u8, blu.0 as u8];
-    let max = [red.1 as u8, grn.1 as u8, blu.1 as u8];
-    (min, max)
-}
-
-pub fn generate_palette3d_hsl(values: &[u8]) -> ([u8; 3], [u8; 3]) {
-    let values: Vec<[f32; 3]> = values
-        .chunks(3)
-        .map(|c| HSL::from_rgb(c))
-        .map(|hsl| [hsl.h as f32, hsl.s as f32, hsl.l as f32])
-        .collect();
-    let hue: Vec<f32> = values.iter().map(|c| c[0]).collect();
-    let sat: Vec<f32> = values.iter().map(|c| c[1]).collect();
-    let lum: Vec<f32> = values.iter().map(|c| c[2]).collect();
-
-    let hue = generate_palette(&hue);
-    let sat = generate_palette(&sat);
-    let lum = generate_palette(&lum);
-
-    let min = HSL {
-        h: hue.0 as f64,
-        s: sat.0 as f64,
-        l: lum.0 as f64,
+    // start from the channel with the largest variance, it can't be orthogonal to the axis
+    let widest = (0..4)
+        .max_by(|a, b| covariance.col(*a)[*a].total_cmp(&covariance.col(*b)[*b]))
+        .unwrap();
+    let mut axis = covariance.col(widest);
+    for _ in 0..8 {
+        axis = (covariance * axis).normalize_or_zero();
+    }
+    if axis == Vec4::ZERO {
+        return (mean, mean);
     }
-    .to_rgb();
 
-    let max = HSL {
-        h: hue.1 as f64,
-        s: sat.1 as f64,
-        l: lum.1 as f64,
+    let (min, max) = points.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+        let t = (*p - mean).dot(axis);
+        (min.min(t), max.max(t))
+    });
+    (mean + axis * min, mean + axis * max)
+}
+
+/// Solve for the pair of endpoints that reproduces a set of points best, by least squares
+///
+/// * `points`: Every point and the weight of the second endpoint in the palette entry it was
+///   mapped to
+///
+/// returns `None` if every point was mapped to the same weight, since any pair of endpoints that
+/// interpolates to their mean is then equally good
+pub fn least_squares_endpoints(
+    points: impl IntoIterator<Item = (Vec4, f32)>,
+) -> Option<(Vec4, Vec4)> {
+    let (mut aa, mut ab, mut bb) = (0.0, 0.0, 0.0);
+    let (mut ax, mut bx) = (Vec4::ZERO, Vec4::ZERO);
+    for (p, w) in points {
+        let v = 1.0 - w;
+        aa += v * v;
+        ab += v * w;
+        bb += w * w;
+        ax += p * v;
+        bx += p * w;
+    }
+    let det = aa * bb - ab * ab;
+    if det.abs() < 1e-6 {
+        return None;
     }
-    .to_rgb();
-    ([min.0, min.1, min.2], [max.0, max.1, max.2])
+    Some(((ax * bb - bx * ab) / det, (bx * aa - ax * ab) / det))
 }
@@ -1,24 +1,54 @@
-use asset_formats::bcn::{bc4, bc5, bc7};
-use asset_formats::dds::dx10::Dx10Header;
-use asset_formats::dds::{dx10, DdsHeader, FullDdsHeader};
-use asset_formats::ImageFormat;
-use clap::{arg, Parser};
+use asset_formats::bcn;
+use asset_formats::color::ColorSpace;
+use asset_formats::dds::DdsFile;
+use asset_formats::ktx2::Supercompression;
+use asset_formats::metrics::Channel;
+use asset_formats::mips::MipFilter;
+use asset_formats::pack::{self, Source};
+use asset_formats::texture::{decode_surface, Texture, TextureBuilder};
+use asset_formats::{color, cubemap, metrics, ImageFormat, TextureLayout};
+use clap::{Parser, ValueEnum};
 use env_logger::Env;
-use image::{open, DynamicImage, EncodableLayout};
-use log::{info, warn};
+use image::{open, DynamicImage};
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::fs;
 use std::fs::File;
-use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-#[derive(Parser, Debug)]
+/// Error reported by tencode before exiting with a failure
+type Error = Box<dyn std::error::Error>;
+
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Input image file
-    file: String,
+    /// Input image files, more than one file requires `--array` or `--cubemap`
+    ///
+    /// DDS inputs may use legacy FourCC or bit mask formats, each of their layers counts as a file
+    #[arg(required_unless_present_any = ["manifest", "red_from", "green_from", "blue_from", "alpha_from"])]
+    files: Vec<String>,
+
+    /// Convert every texture listed in a TOML manifest instead of the input files
+    #[arg(long, conflicts_with_all = ["files", "out", "heat_map"])]
+    manifest: Option<PathBuf>,
 
-    /// DDS output file
+    /// Convert every texture of the manifest, even the ones whose output is up to date
+    #[arg(long, requires = "manifest")]
+    force: bool,
+
+    /// Output file
     #[arg(short, long)]
     out: Option<String>,
 
+    /// Output container, by default picked from the output file's extension or the codec
+    #[arg(long, value_enum)]
+    container: Option<Container>,
+
+    /// Supercompress each mip level with zstd at the given level, KTX2 only
+    #[arg(long)]
+    zstd: Option<i32>,
+
     /// Enable Red channel
     #[arg(short)]
     red: bool,
@@ -33,72 +63,638 @@ pub struct Args {
     alpha: bool,
 
     /// Enable Luma (grayscale) Channel
-    #[arg(short, conflicts_with_all = ["red", "green", "blue"])]
+    #[arg(short, conflicts_with_all = ["red", "green", "blue", "red_from"])]
     luma: bool,
 
+    /// Fill the red channel from another image and enable it
+    ///
+    /// `FILE:CHANNEL` takes channel `r`, `g`, `b`, `a` or `l` of an image, `FILE` alone takes its
+    /// luma and a number fills the channel with that value. Channels without a source are copied
+    /// from the input files, a texture can be packed without any input files.
+    #[arg(long, value_name = "SOURCE", conflicts_with = "manifest")]
+    red_from: Option<String>,
+    /// Fill the green channel like `--red-from`
+    #[arg(long, value_name = "SOURCE", conflicts_with = "manifest")]
+    green_from: Option<String>,
+    /// Fill the blue channel like `--red-from`
+    #[arg(long, value_name = "SOURCE", conflicts_with = "manifest")]
+    blue_from: Option<String>,
+    /// Fill the alpha channel like `--red-from`
+    #[arg(long, value_name = "SOURCE", conflicts_with = "manifest")]
+    alpha_from: Option<String>,
+
+    /// Flip channels to `1 - value` once they are packed, made up of `r`, `g`, `b` and `a`
+    #[arg(long, value_name = "CHANNELS")]
+    invert: Option<String>,
+
+    /// Precision of uncompressed channels, use 16 bit or float to keep 16 bit and HDR inputs
+    #[arg(long, value_enum, default_value_t = Depth::U8, conflicts_with = "compress")]
+    depth: Depth,
+
     /// Enable compression
     #[arg(short)]
     compress: bool,
+
+    /// Block compression codec, by default one is picked from the enabled channels
+    #[arg(long, value_enum, requires = "compress")]
+    codec: Option<Codec>,
+
+    /// Block footprint of `--codec astc`, larger blocks make smaller files at a lower quality
+    #[arg(long, value_enum, default_value_t = BlockSize::Astc4x4)]
+    block_size: BlockSize,
+
+    /// Effort spent on the endpoints of Bc1 to Bc5 and Bc7 blocks, slower levels lower the error
+    #[arg(long, value_enum, default_value_t = Quality::Normal)]
+    quality: Quality,
+
+    /// Generate a full mip chain
+    #[arg(short, long)]
+    mips: bool,
+
+    /// Filter used to downsample mip levels
+    #[arg(long, value_enum, default_value_t = Filter::Box)]
+    mip_filter: Filter,
+
+    /// Store the input files as the layers of a texture array
+    #[arg(long, conflicts_with = "cubemap")]
+    array: bool,
+
+    /// Build a cube map from either 6 face images (+X, -X, +Y, -Y, +Z, -Z) or a single cross image
+    #[arg(long)]
+    cubemap: bool,
+
+    /// Store the color channels as sRGB, mip levels are still filtered on linear values
+    #[arg(long)]
+    srgb: bool,
+
+    /// Treat the input as a tangent space normal map, storing X and Y as Bc5 with renormalized mips
+    #[arg(long, conflicts_with_all = ["luma", "srgb", "codec"])]
+    normal_map: bool,
+
+    /// Store Bc4 and Bc5 as signed values, inputs are mapped from 0..1 to -1..1
+    #[arg(long)]
+    snorm: bool,
+
+    /// Decode the encoded texture and print its error against the input images
+    #[arg(long)]
+    report: bool,
+
+    /// Write a heat map of the per pixel error of the first layer to an image, implies `--report`
+    #[arg(long)]
+    heat_map: Option<String>,
+
+    /// Number of threads used to encode blocks, 0 uses one per core
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
 }
 
-#[derive(Debug)]
-struct Dds {
-    magic: u32,
-    header: DdsHeader,
-    dx10_header: Option<Dx10Header>,
-    data: Vec<Vec<u8>>,
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Container {
+    Dds,
+    Ktx2,
 }
 
-fn encode_dds(data: &[DynamicImage], format: ImageFormat) -> Dds {
-    if data.len() != 1 {
-        panic!("unsupported number of images added to file: {}", data.len());
+impl Container {
+    fn extension(&self) -> &'static str {
+        match self {
+            Container::Dds => "dds",
+            Container::Ktx2 => "ktx2",
+        }
     }
-    let header = FullDdsHeader::new(data[0].width(), data[0].height(), None, format);
-    let mut array = vec![];
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum Codec {
+    Bc1,
+    Bc3,
+    Bc4,
+    Bc5,
+    /// Unsigned half float, for HDR inputs
+    Bc6h,
+    /// Signed half float, for HDR inputs
+    Bc6hSigned,
+    Bc7,
+    /// Etc2 rgb(a), for Android and other mobile GPUs
+    Etc2,
+    /// Eac for one or two channels, for Android and other mobile GPUs
+    Eac,
+    /// Astc rgb(a) with the footprint picked by `--block-size`
+    Astc,
+}
+
+impl Codec {
+    /// Container used when none is given, Etc2 and Eac can't be stored in DDS files and few tools
+    /// read Astc from them
+    fn default_container(&self) -> Container {
+        match self {
+            Codec::Etc2 | Codec::Eac | Codec::Astc => Container::Ktx2,
+            _ => Container::Dds,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+enum Depth {
+    /// 8 bit unsigned normalized
+    #[value(name = "8")]
+    #[serde(rename = "8")]
+    U8,
+    /// 16 bit unsigned normalized, for heightmaps and other smooth gradients
+    #[value(name = "16")]
+    #[serde(rename = "16")]
+    U16,
+    /// Half float
+    #[value(name = "16f")]
+    #[serde(rename = "16f")]
+    F16,
+    /// Full float, rgba only
+    #[value(name = "32f")]
+    #[serde(rename = "32f")]
+    F32,
+}
 
-    for img in data {
-        // convert byte format
-        let bytes = match format {
-            ImageFormat::Rgb8 => img.to_rgb8().to_vec(),
-            ImageFormat::Rgba8 => img.to_rgba8().to_vec(),
-            ImageFormat::Luma8 => img.to_luma8().to_vec(),
-            ImageFormat::LumaAlpha8 => img.to_luma_alpha8().to_vec(),
-            ImageFormat::Luma8_Bc4 => bytemuck::cast_slice(&bc4::encode(&img)).to_vec(),
-            ImageFormat::LumaAlpha8_Bc5 => {
-                bytemuck::cast_slice((&bc5::encode_grayscale(&img))).to_vec()
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone)]
+enum BlockSize {
+    /// 8 bits per pixel
+    #[value(name = "4x4")]
+    #[serde(rename = "4x4")]
+    Astc4x4,
+    /// 3.56 bits per pixel
+    #[value(name = "6x6")]
+    #[serde(rename = "6x6")]
+    Astc6x6,
+    /// 2 bits per pixel
+    #[value(name = "8x8")]
+    #[serde(rename = "8x8")]
+    Astc8x8,
+}
+
+impl BlockSize {
+    fn format(&self) -> ImageFormat {
+        match self {
+            BlockSize::Astc4x4 => ImageFormat::Rgba8_Astc4x4,
+            BlockSize::Astc6x6 => ImageFormat::Rgba8_Astc6x6,
+            BlockSize::Astc8x8 => ImageFormat::Rgba8_Astc8x8,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum Filter {
+    Box,
+    Triangle,
+    Lanczos,
+}
+
+impl From<Filter> for MipFilter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Box => MipFilter::Box,
+            Filter::Triangle => MipFilter::Triangle,
+            Filter::Lanczos => MipFilter::Lanczos,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum Quality {
+    Fast,
+    Normal,
+    Slow,
+}
+
+impl From<Quality> for bcn::Quality {
+    fn from(quality: Quality) -> Self {
+        match quality {
+            Quality::Fast => bcn::Quality::Fast,
+            Quality::Normal => bcn::Quality::Normal,
+            Quality::Slow => bcn::Quality::Slow,
+        }
+    }
+}
+
+/// A batch of textures to convert
+///
+/// ```toml
+/// # settings shared by every texture
+/// [defaults]
+/// channels = "rgba"
+/// codec = "bc7"
+/// mips = true
+/// srgb = true
+///
+/// [[texture]]
+/// inputs = ["textures/*.png"]
+/// out_dir = "build/textures"
+///
+/// [[texture]]
+/// inputs = ["normals/*.png"]
+/// out_dir = "build/normals"
+/// channels = "rg"
+/// codec = "bc5"
+/// srgb = false
+///
+/// # settings of single files, applied on top of the texture that lists them
+/// [[override]]
+/// inputs = ["textures/ui_*.png"]
+/// mips = false
+/// ```
+///
+/// Paths are relative to the manifest.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    defaults: Options,
+    #[serde(default, rename = "texture")]
+    textures: Vec<ManifestEntry>,
+    #[serde(default, rename = "override")]
+    overrides: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ManifestEntry {
+    /// Input files or glob patterns
+    inputs: Vec<String>,
+    #[serde(flatten)]
+    options: Options,
+}
+
+/// Conversion settings of a manifest, unset settings fall back to the enclosing scope
+#[derive(Deserialize, Debug, Default, Clone)]
+struct Options {
+    /// Enabled channels, made up of `r`, `g`, `b`, `a` and `l`
+    channels: Option<String>,
+    /// Channels flipped to `1 - value`, made up of `r`, `g`, `b` and `a`
+    invert: Option<String>,
+    depth: Option<Depth>,
+    /// Defaults to true when a codec is set
+    compress: Option<bool>,
+    codec: Option<Codec>,
+    block_size: Option<BlockSize>,
+    quality: Option<Quality>,
+    mips: Option<bool>,
+    mip_filter: Option<Filter>,
+    srgb: Option<bool>,
+    normal_map: Option<bool>,
+    snorm: Option<bool>,
+    /// Split a single cross image into the faces of a cube map
+    cubemap: Option<bool>,
+    container: Option<Container>,
+    zstd: Option<i32>,
+    /// Directory outputs are written to, by default they are written next to their input
+    out_dir: Option<PathBuf>,
+}
+
+impl Options {
+    /// Fill the unset settings from another set of options
+    fn or(&self, fallback: &Options) -> Options {
+        Options {
+            channels: self.channels.clone().or(fallback.channels.clone()),
+            invert: self.invert.clone().or(fallback.invert.clone()),
+            depth: self.depth.or(fallback.depth),
+            compress: self.compress.or(fallback.compress),
+            codec: self.codec.or(fallback.codec),
+            block_size: self.block_size.or(fallback.block_size),
+            quality: self.quality.or(fallback.quality),
+            mips: self.mips.or(fallback.mips),
+            mip_filter: self.mip_filter.or(fallback.mip_filter),
+            srgb: self.srgb.or(fallback.srgb),
+            normal_map: self.normal_map.or(fallback.normal_map),
+            snorm: self.snorm.or(fallback.snorm),
+            cubemap: self.cubemap.or(fallback.cubemap),
+            container: self.container.or(fallback.container),
+            zstd: self.zstd.or(fallback.zstd),
+            out_dir: self.out_dir.clone().or(fallback.out_dir.clone()),
+        }
+    }
+
+    /// Overwrite the texture settings of command line arguments
+    fn apply(&self, args: &mut Args) -> Result<(), Error> {
+        let channels = self.channels.as_deref().unwrap_or("rgba");
+        if let Some(c) = channels.chars().find(|c| !"rgbal".contains(*c)) {
+            return Err(format!("unknown channel '{c}' in \"{channels}\"").into());
+        }
+        args.red = channels.contains('r');
+        args.green = channels.contains('g');
+        args.blue = channels.contains('b');
+        args.alpha = channels.contains('a');
+        args.luma = channels.contains('l');
+        args.invert = self.invert.clone();
+        args.depth = self.depth.unwrap_or(Depth::U8);
+        args.codec = self.codec;
+        args.compress = self.compress.unwrap_or(self.codec.is_some());
+        args.block_size = self.block_size.unwrap_or(BlockSize::Astc4x4);
+        args.quality = self.quality.unwrap_or(Quality::Normal);
+        args.mips = self.mips.unwrap_or(false);
+        args.mip_filter = self.mip_filter.unwrap_or(Filter::Box);
+        args.srgb = self.srgb.unwrap_or(false);
+        args.normal_map = self.normal_map.unwrap_or(false);
+        args.snorm = self.snorm.unwrap_or(false);
+        args.cubemap = self.cubemap.unwrap_or(false);
+        args.array = false;
+        args.container = self.container;
+        args.zstd = self.zstd;
+        Ok(())
+    }
+}
+
+/// Convert every texture of a manifest, skipping outputs that are newer than their input and the
+/// manifest itself
+///
+/// A texture that fails to convert doesn't stop the rest of the batch, but the batch still fails.
+fn batch(args: &Args, path: &Path) -> Result<(), Error> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read manifest {}: {e}", path.display()))?;
+    let manifest: Manifest =
+        toml::from_str(&text).map_err(|e| format!("invalid manifest {}: {e}", path.display()))?;
+    let root = path.parent().unwrap_or(Path::new(""));
+
+    let overrides = manifest
+        .overrides
+        .iter()
+        .map(|entry| Ok((expand(root, &entry.inputs)?, &entry.options)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let (mut converted, mut skipped, mut failed) = (0, 0, 0);
+    for texture in &manifest.textures {
+        for input in expand(root, &texture.inputs)? {
+            let mut options = texture.options.or(&manifest.defaults);
+            for (inputs, overrides) in &overrides {
+                if inputs.contains(&input) {
+                    options = overrides.or(&options);
+                }
             }
-            ImageFormat::Rg8_Bc5 => bytemuck::cast_slice((&bc5::encode_color(&img))).to_vec(),
-            ImageFormat::Rgb8_Bc7 => bytemuck::cast_slice(&bc7::encode(&img)).to_vec(),
-        };
-        array.push(bytes);
+
+            let container = options
+                .container
+                .or(options.codec.map(|codec| codec.default_container()))
+                .unwrap_or(Container::Dds);
+            let Some(file_name) = input.file_name() else {
+                continue;
+            };
+            let name = format!("{}.{}", file_name.to_string_lossy(), container.extension());
+            let out = match &options.out_dir {
+                Some(dir) => root.join(dir).join(name),
+                None => input.with_file_name(name),
+            };
+            if !args.force && is_newer(&out, &[&input, path]) {
+                info!(
+                    "Skipping {}, {} is up to date",
+                    input.display(),
+                    out.display()
+                );
+                skipped += 1;
+                continue;
+            }
+
+            let mut job = args.clone();
+            job.files = vec![input.to_string_lossy().into_owned()];
+            job.out = Some(out.to_string_lossy().into_owned());
+            let result = options.apply(&mut job).and_then(|()| {
+                if let Some(dir) = out.parent() {
+                    fs::create_dir_all(dir)
+                        .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+                }
+                convert(&job)
+            });
+            match result {
+                Ok(()) => converted += 1,
+                Err(e) => {
+                    error!("Failed to convert {}: {e}", input.display());
+                    failed += 1;
+                }
+            }
+        }
+    }
+    info!("Converted {converted} textures, skipped {skipped} up to date textures");
+    if failed > 0 {
+        return Err(format!("{failed} textures failed to convert").into());
     }
-    Dds {
-        magic: header.magic,
-        header: header.header,
-        dx10_header: header.dx10_header,
-        data: array,
+    Ok(())
+}
+
+/// Resolve files and glob patterns relative to a directory
+fn expand(root: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![];
+    for pattern in patterns {
+        let pattern = root.join(pattern);
+        let matches = glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| format!("invalid glob pattern {}: {e}", pattern.display()))?;
+        let len = files.len();
+        files.extend(matches.filter_map(Result::ok));
+        if files.len() == len {
+            warn!("{} doesn't match any files", pattern.display());
+        }
     }
+    Ok(files)
 }
 
-fn main() {
+/// Check if a file was modified after all of the given files
+fn is_newer(file: &Path, than: &[&Path]) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match modified(file) {
+        Some(time) => than
+            .iter()
+            .all(|path| modified(path).is_some_and(|t| time > t)),
+        None => false,
+    }
+}
+
+/// Open an input image
+///
+/// DDS files are read with our own reader, so compressed and legacy formats can be used as input.
+/// Every layer (or cube face) of a DDS file becomes its own image, mip levels are dropped.
+fn open_images(file: &str) -> Result<Vec<DynamicImage>, Error> {
+    let is_dds = Path::new(file)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dds"));
+    if !is_dds {
+        let img = open(file).map_err(|e| format!("failed to open {file}: {e}"))?;
+        return Ok(vec![img]);
+    }
+    let dds = File::open(file)
+        .map_err(asset_formats::Error::from)
+        .and_then(|reader| Ok(DdsFile::read(reader)?))
+        .map_err(|e| format!("failed to open {file}: {e}"))?;
+    let images = dds
+        .surfaces
+        .iter()
+        .filter(|surface| surface.mip == 0)
+        .map(|surface| decode_surface(&surface.data, dds.format, surface.width, surface.height))
+        .collect::<asset_formats::Result<_>>()?;
+    Ok(images)
+}
+
+/// Parse the source of a packed channel and open its image
+fn open_source(arg: &str) -> Result<Source, Error> {
+    if let Ok(value) = arg.parse::<f32>() {
+        return Ok(Source::Constant(value));
+    }
+    // a longer suffix is part of the path, like the drive of a windows path
+    let (file, channel) = match arg.rsplit_once(':') {
+        Some((file, channel)) if channel.len() == 1 => (file, channel),
+        _ => (arg, "l"),
+    };
+    let channel = match channel {
+        "r" => Channel::Red,
+        "g" => Channel::Green,
+        "b" => Channel::Blue,
+        "a" => Channel::Alpha,
+        "l" => Channel::Luma,
+        _ => return Err(format!("unknown channel '{channel}' in \"{arg}\"").into()),
+    };
+    let img = open_images(file)?
+        .into_iter()
+        .next()
+        .ok_or(format!("{file} doesn't contain an image"))?;
+    Ok(Source::Channel(img, channel))
+}
+
+/// Parse a set of channels into flags for red, green, blue and alpha
+fn channel_mask(channels: &str) -> Result<[bool; 4], Error> {
+    if let Some(c) = channels.chars().find(|c| !"rgba".contains(*c)) {
+        return Err(format!("unknown channel '{c}' in \"{channels}\"").into());
+    }
+    Ok(['r', 'g', 'b', 'a'].map(|c| channels.contains(c)))
+}
+
+/// Print the error of the full size surface of every layer against the image it was encoded from
+fn report(images: &[DynamicImage], texture: &Texture, heat_map: Option<&str>) -> Result<(), Error> {
+    let format = texture.format;
+    for (layer, img) in images.iter().enumerate() {
+        // float images hold linear values, they are compared as the sRGB values that were encoded
+        let img = match (format.color_space(), img) {
+            (ColorSpace::Srgb, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) => {
+                let linear = img.to_rgba32f();
+                let srgb = color::convert(&linear, ColorSpace::Linear, ColorSpace::Srgb);
+                &DynamicImage::ImageRgba32F(srgb)
+            }
+            _ => img,
+        };
+        let decoded = texture
+            .decode(layer as u32, 0)
+            .ok_or("texture is missing a layer")??;
+        let metrics = metrics::compare(img, &decoded, format.channels())?;
+        info!("Layer {layer} error:\n{metrics}");
+
+        if let (0, Some(path)) = (layer, heat_map) {
+            metrics::error_map(img, &decoded, format.channels())?
+                .save(path)
+                .map_err(|e| format!("failed to write heat map {path}: {e}"))?;
+            info!("Wrote error heat map to {path}");
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     let args = Args::parse();
-    let img = open(&args.file).unwrap();
-    let out_file = args.out.unwrap_or_else(|| format!("{}.dds", args.file));
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), Error> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build_global()?;
+    match &args.manifest {
+        Some(manifest) => batch(args, manifest),
+        None => convert(args),
+    }
+}
+
+/// Convert the input files of a set of arguments into a single texture
+fn convert(args: &Args) -> Result<(), Error> {
+    let mut images = vec![];
+    for file in &args.files {
+        images.extend(open_images(file)?);
+    }
+
+    // packed channels are always enabled
+    let mut args = args.clone();
+    let from = [
+        &args.red_from,
+        &args.green_from,
+        &args.blue_from,
+        &args.alpha_from,
+    ];
+    let mut sources: [Option<Source>; 4] = Default::default();
+    for ((source, arg), name) in sources
+        .iter_mut()
+        .zip(from)
+        .zip(["red", "green", "blue", "alpha"])
+    {
+        if let Some(arg) = arg {
+            info!("Packing {arg} into the {name} channel");
+            *source = Some(open_source(arg)?);
+        }
+    }
+    let invert = channel_mask(args.invert.as_deref().unwrap_or(""))?;
+    if sources.iter().any(Option::is_some) || invert.contains(&true) {
+        images = match images.is_empty() {
+            true => vec![pack::pack(None, &sources, invert)?],
+            false => images
+                .iter()
+                .map(|img| pack::pack(Some(img), &sources, invert))
+                .collect::<asset_formats::Result<_>>()?,
+        };
+    }
+    args.red |= args.red_from.is_some();
+    args.green |= args.green_from.is_some();
+    args.blue |= args.blue_from.is_some();
+    args.alpha |= args.alpha_from.is_some();
+    let args = &args;
 
-    info!("Encoding {in} with mode {r}{g}{b}{l}{a}{d}{w}{c} to {out_file}", in = args.file,
+    let container = args
+        .container
+        .or_else(|| match args.out.as_deref() {
+            Some(out) if out.ends_with(".ktx2") => Some(Container::Ktx2),
+            Some(out) if out.ends_with(".dds") => Some(Container::Dds),
+            _ => None,
+        })
+        .or(args.codec.map(|codec| codec.default_container()))
+        .unwrap_or(Container::Dds);
+    if args.zstd.is_some() && container != Container::Ktx2 {
+        return Err("zstd supercompression is only supported by ktx2 files".into());
+    }
+    let out_file = match (&args.out, args.files.first()) {
+        (Some(out), _) => out.clone(),
+        (None, Some(file)) => format!("{file}.{}", container.extension()),
+        (None, None) => return Err("a texture without input files needs --out".into()),
+    };
+
+    let input = match args.files.is_empty() {
+        true => "packed channels".to_string(),
+        false => args.files.join(", "),
+    };
+    info!(
+        "Encoding {input} with mode {r}{g}{b}{l}{a}{d}{w}{c} to {out_file}",
         r = if args.red { "r" } else { "" },
         g = if args.green { "g" } else { "" },
         b = if args.blue { "b" } else { "" },
         l = if args.luma { "l" } else { "" },
         a = if args.alpha { "a" } else { "" },
         c = if args.compress { "c" } else { "" },
-        d = 'u',
-        w = '8',);
-
-    if args.compress {
-        warn!("Compression is currently unsupported");
-    }
+        d = match args.depth {
+            Depth::F16 | Depth::F32 => 'f',
+            _ => 'u',
+        },
+        w = match args.depth {
+            Depth::U8 => "8",
+            Depth::U16 | Depth::F16 => "16",
+            Depth::F32 => "32",
+        },
+    );
 
     let format = match (
         args.red || args.luma,
@@ -106,26 +702,151 @@ fn main() {
         args.blue,
         args.alpha,
         args.compress,
+        args.codec,
     ) {
-        (true, false, false, false, false) => ImageFormat::Luma8,
-        (true, false, false, false, true) => ImageFormat::Luma8_Bc4,
-        (true, false, false, true, false) => ImageFormat::LumaAlpha8,
-        (true, false, false, true, true) => ImageFormat::LumaAlpha8_Bc5,
-        (true, true, false, false, true) => ImageFormat::Rg8_Bc5,
-        (true, true, true, false, true) => ImageFormat::Rgb8_Bc7,
-        (true, true, true, false, false) => ImageFormat::Rgb8,
-        (true, true, true, true, _) => ImageFormat::Rgba8,
+        // normal maps only store x and y, z is rebuilt when sampling
+        _ if args.normal_map && args.snorm => ImageFormat::Rg8Snorm_Bc5,
+        _ if args.normal_map => ImageFormat::Rg8_Bc5,
+        (true, false, false, false, true, None | Some(Codec::Bc4)) if args.snorm => {
+            ImageFormat::Luma8Snorm_Bc4
+        }
+        (true, true, false, false, true, None | Some(Codec::Bc5)) if args.snorm => {
+            ImageFormat::Rg8Snorm_Bc5
+        }
+        _ if args.snorm => {
+            return Err("--snorm only applies to compressed Bc4 and Bc5 textures".into())
+        }
+        (true, false, false, false, false, _) => ImageFormat::Luma8,
+        (true, false, false, false, true, None | Some(Codec::Bc4)) => ImageFormat::Luma8_Bc4,
+        (true, false, false, false, true, Some(Codec::Eac)) => ImageFormat::R11_Eac,
+        (true, false, false, true, false, _) => ImageFormat::LumaAlpha8,
+        (true, false, false, true, true, None | Some(Codec::Bc5)) => ImageFormat::LumaAlpha8_Bc5,
+        (true, true, false, false, true, None | Some(Codec::Bc5)) => ImageFormat::Rg8_Bc5,
+        (true, true, false, false, true, Some(Codec::Eac)) => ImageFormat::Rg11_Eac,
+        (true, true, true, false, true, None | Some(Codec::Bc7)) => ImageFormat::Rgb8_Bc7,
+        (true, true, true, false, true, Some(Codec::Bc1)) => ImageFormat::Rgb8_Bc1,
+        (true, true, true, false, true, Some(Codec::Bc6h)) => ImageFormat::RgbUF_Bc6,
+        (true, true, true, false, true, Some(Codec::Bc6hSigned)) => ImageFormat::RgbF_Bc6,
+        (true, true, true, false, true, Some(Codec::Etc2)) => ImageFormat::Rgb8_Etc2,
+        (true, true, true, true, true, None | Some(Codec::Bc7)) => ImageFormat::Rgba8_Bc7,
+        (true, true, true, true, true, Some(Codec::Bc1)) => ImageFormat::Rgba8_Bc1,
+        (true, true, true, true, true, Some(Codec::Bc3)) => ImageFormat::Rgba8_Bc3,
+        (true, true, true, true, true, Some(Codec::Etc2)) => ImageFormat::Rgba8_Etc2,
+        (true, true, true, _, true, Some(Codec::Astc)) => args.block_size.format(),
+        (true, true, true, false, false, _) => ImageFormat::Rgb8,
+        (_, _, _, _, true, Some(codec)) => {
+            return Err(format!("{codec:?} can't store the enabled channels").into())
+        }
+        (true, true, true, true, _, _) => ImageFormat::Rgba8,
         _ => ImageFormat::Rgba8,
     };
-    let dds = encode_dds(&[img], format);
-    // write out file
-    let mut file = File::create(out_file).unwrap();
-    file.write_all(&dds.magic.to_ne_bytes()).unwrap();
-    file.write_all(bytemuck::bytes_of(&dds.header)).unwrap();
-    if let Some(dx10_header) = dds.dx10_header {
-        file.write_all(bytemuck::bytes_of(&dx10_header)).unwrap();
+
+    // wider formats are picked from the enabled channels the same way
+    let format = match (args.depth, format) {
+        (Depth::U8, _) => format,
+        _ if format.is_compressed() => {
+            return Err("--depth only applies to uncompressed textures".into())
+        }
+        (Depth::U16, ImageFormat::Luma8) => ImageFormat::R16,
+        (Depth::F16, ImageFormat::Luma8) => ImageFormat::R16F,
+        (Depth::F32, ImageFormat::Luma8) => {
+            return Err("32 bit float textures are rgba only, use --depth 16f instead".into())
+        }
+        (_, ImageFormat::LumaAlpha8) => {
+            return Err("luma alpha textures can only be stored with 8 bits".into())
+        }
+        (Depth::U16, _) if !args.blue && !args.alpha => ImageFormat::Rg16,
+        (Depth::U16, _) => ImageFormat::Rgba16,
+        (Depth::F16, _) => ImageFormat::Rgba16F,
+        (Depth::F32, _) => ImageFormat::Rgba32F,
+    };
+    if container == Container::Dds && !format.dds_supported() {
+        return Err(
+            format!("{format:?} can't be stored in a dds file, use --container ktx2").into(),
+        );
+    }
+    let (layout, images) = if args.cubemap {
+        let faces: Vec<DynamicImage> = match images.len() {
+            1 => cubemap::faces_from_cross(&images[0])
+                .ok_or("cube map image is not a horizontal or vertical cross")?
+                .into(),
+            _ => images,
+        };
+        (TextureLayout::Cubemap, faces)
+    } else if args.array {
+        (TextureLayout::Array(images.len() as u32), images)
+    } else if images.len() == 1 {
+        (TextureLayout::Texture2D, images)
+    } else {
+        return Err("multiple input files need either --array or --cubemap".into());
+    };
+
+    // texture arrays need a dxgi format and there isn't one for 24 bit rgb
+    let format = match (container, layout, format) {
+        (Container::Dds, TextureLayout::Array(_), ImageFormat::Rgb8) => {
+            warn!("Texture arrays can't store Rgb8, storing as Rgba8 instead");
+            ImageFormat::Rgba8
+        }
+        _ => format,
+    };
+
+    // neither is there an sRGB format for 24 bit rgb
+    let format = match (args.srgb, format) {
+        (false, _) => format,
+        (true, ImageFormat::Rgb8) => {
+            warn!("There is no sRGB Rgb8 format, storing as Srgba8 instead");
+            ImageFormat::Srgba8
+        }
+        (true, _) => format
+            .with_color_space(ColorSpace::Srgb)
+            .ok_or(format!("{format:?} has no sRGB variant"))?,
+    };
+
+    // luma formats would store the luma of a packed image instead of its red channel
+    let images = match (&args.red_from, format.channels()[0]) {
+        (Some(_), Channel::Luma) => images.iter().map(pack::red_to_luma).collect(),
+        _ => images,
+    };
+
+    // Astc blocks store alpha whenever a texel isn't opaque, so it's dropped when it isn't enabled.
+    // Only float images may stay float, those are treated as linear and converted to sRGB
+    let images = match (args.codec, args.alpha) {
+        (Some(Codec::Astc), false) => images
+            .into_iter()
+            .map(|img| match img {
+                DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                    DynamicImage::ImageRgb32F(img.to_rgb32f())
+                }
+                _ => DynamicImage::ImageRgb16(img.to_rgb16()),
+            })
+            .collect(),
+        _ => images,
+    };
+
+    let mips = if args.mips { u32::MAX } else { 1 };
+    let texture = TextureBuilder::new(format)
+        .images(images.clone())
+        .layout(layout)
+        .mips(mips)
+        .mip_filter(args.mip_filter.into())
+        .quality(args.quality.into())
+        .normal_map(args.normal_map)
+        .build()?;
+    if args.report || args.heat_map.is_some() {
+        report(&images, &texture, args.heat_map.as_deref())?;
     }
-    for buf in dds.data {
-        file.write_all(&buf).unwrap();
+
+    let file = File::create(&out_file).map_err(|e| format!("failed to create {out_file}: {e}"))?;
+    match container {
+        Container::Dds => texture.write_dds(file),
+        Container::Ktx2 => {
+            let supercompression = match args.zstd {
+                Some(level) => Supercompression::Zstd(level),
+                None => Supercompression::None,
+            };
+            texture.write_ktx2(file, supercompression)
+        }
     }
+    .map_err(|e| format!("failed to write {out_file}: {e}"))?;
+    Ok(())
 }
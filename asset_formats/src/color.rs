@@ -0,0 +1,60 @@
+//! Color space conversions
+
+use image::{Rgba, Rgba32FImage};
+use std::sync::OnceLock;
+
+/// How the color channels of an image are encoded, alpha is always linear
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Values are proportional to light intensity
+    #[default]
+    Linear,
+    /// Values are gamma encoded with the sRGB transfer function
+    Srgb,
+}
+
+/// Decode a normalized sRGB value to linear
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a normalized linear value with the sRGB transfer function
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear value of each 8 bit channel value, scaled back up to the 0 to 255 range
+pub(crate) fn linear_table(color_space: ColorSpace) -> &'static [f32; 256] {
+    static LINEAR: OnceLock<[f32; 256]> = OnceLock::new();
+    static SRGB: OnceLock<[f32; 256]> = OnceLock::new();
+    match color_space {
+        ColorSpace::Linear => LINEAR.get_or_init(|| std::array::from_fn(|v| v as f32)),
+        ColorSpace::Srgb => {
+            SRGB.get_or_init(|| std::array::from_fn(|v| srgb_to_linear(v as f32 / 255.0) * 255.0))
+        }
+    }
+}
+
+/// Convert the color channels of an image from one color space to another
+pub fn convert(img: &Rgba32FImage, from: ColorSpace, to: ColorSpace) -> Rgba32FImage {
+    let transfer: fn(f32) -> f32 = match (from, to) {
+        (ColorSpace::Srgb, ColorSpace::Linear) => srgb_to_linear,
+        (ColorSpace::Linear, ColorSpace::Srgb) => linear_to_srgb,
+        _ => return img.clone(),
+    };
+    let mut img = img.clone();
+    for Rgba(pixel) in img.pixels_mut() {
+        for c in &mut pixel[..3] {
+            *c = transfer(c.clamp(0.0, 1.0));
+        }
+    }
+    img
+}
@@ -0,0 +1,47 @@
+//! Building cube map faces out of single images
+
+use image::DynamicImage;
+
+/// Split a cross layout image into the 6 faces of a cube map
+///
+/// Both horizontal (4x3 faces) and vertical (3x4 faces) crosses are supported:
+///
+/// ```text
+///     +Y                +Y
+/// -X  +Z  +X  -Z    -X  +Z  +X
+///     -Y                -Y
+///                       -Z
+/// ```
+///
+/// In a vertical cross the -Z face is stored upside down, so it gets rotated back.
+///
+/// Returns the faces in the order +X, -X, +Y, -Y, +Z, -Z or `None` if the image isn't shaped like a
+/// cross.
+pub fn faces_from_cross(img: &DynamicImage) -> Option<[DynamicImage; 6]> {
+    let (w, h) = (img.width(), img.height());
+    let face = |x: u32, y: u32, size: u32| img.crop_imm(x * size, y * size, size, size);
+
+    if w % 4 == 0 && w / 4 * 3 == h {
+        let size = w / 4;
+        Some([
+            face(2, 1, size),
+            face(0, 1, size),
+            face(1, 0, size),
+            face(1, 2, size),
+            face(1, 1, size),
+            face(3, 1, size),
+        ])
+    } else if w % 3 == 0 && w / 3 * 4 == h {
+        let size = w / 3;
+        Some([
+            face(2, 1, size),
+            face(0, 1, size),
+            face(1, 0, size),
+            face(1, 2, size),
+            face(1, 1, size),
+            face(1, 3, size).rotate180(),
+        ])
+    } else {
+        None
+    }
+}
@@ -1,14 +1,95 @@
 //! DirectDraw Surface image format
 
 use crate::dds::dx10::DxgiFormat::{DXGI_FORMAT_BC5_UNORM, DXGI_FORMAT_BC7_UNORM};
-use crate::dds::dx10::{Dx10Header, DxgiFormat, ResourceDimension};
-use crate::ImageFormat;
+use crate::dds::dx10::{Dx10Header, DxgiFormat, ResourceDimension, RESOURCE_MISC_TEXTURECUBE};
+use crate::texture::{check_surfaces, TextureError};
+use crate::{ImageFormat, TextureLayout};
 use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
 use clap::builder::styling::Color::Rgb;
 use log::warn;
+use std::io::Write;
+
+mod read;
+pub use read::*;
 
 pub const DDS_MAGIC: u32 = 0x20534444;
+/// FourCC marking that a [`Dx10Header`] follows the base header
+pub const DX10_FOURCC: u32 = 0x30315844;
+/// FourCC of legacy Bc1 textures
+pub const DXT1_FOURCC: u32 = 0x31545844;
+/// FourCC of legacy Bc2 textures
+pub const DXT3_FOURCC: u32 = 0x33545844;
+/// FourCC of legacy Bc3 textures
+pub const DXT5_FOURCC: u32 = 0x35545844;
+/// FourCC of legacy Bc4 textures, from ATI's original 3Dc+ extension
+pub const ATI1_FOURCC: u32 = 0x31495441;
+/// FourCC of legacy Bc5 textures, from ATI's original 3Dc extension
+pub const ATI2_FOURCC: u32 = 0x32495441;
+/// FourCC of legacy unsigned Bc4 textures
+pub const BC4U_FOURCC: u32 = 0x55344342;
+/// FourCC of legacy signed Bc4 textures
+pub const BC4S_FOURCC: u32 = 0x53344342;
+/// FourCC of legacy unsigned Bc5 textures
+pub const BC5U_FOURCC: u32 = 0x55354342;
+/// FourCC of legacy signed Bc5 textures
+pub const BC5S_FOURCC: u32 = 0x53354342;
+/// `D3DFMT_A16B16G16R16`, legacy files store some D3D9 formats in place of a FourCC
+pub const D3DFMT_A16B16G16R16: u32 = 36;
+/// `D3DFMT_R16F`
+pub const D3DFMT_R16F: u32 = 111;
+/// `D3DFMT_A16B16G16R16F`
+pub const D3DFMT_A16B16G16R16F: u32 = 113;
+/// `D3DFMT_A32B32G32R32F`
+pub const D3DFMT_A32B32G32R32F: u32 = 116;
+
+/// A DDS texture, ready to be written to a file
+#[derive(Debug)]
+pub struct Dds {
+    pub header: FullDdsHeader,
+    /// Data of every surface, ordered by layer and then by mip level
+    pub data: Vec<Vec<u8>>,
+}
+
+impl Dds {
+    /// Wrap encoded surfaces, ordered by layer and then by mip level, in a DDS file
+    ///
+    /// Fails if the surfaces don't make up a whole texture or the format has no DDS
+    /// representation, texture arrays also need a DXGI format.
+    ///
+    /// * `width`: Width of the full size surfaces
+    /// * `height`: Height of the full size surfaces
+    pub fn new(
+        surfaces: Vec<Vec<u8>>,
+        width: u32,
+        height: u32,
+        format: ImageFormat,
+        layout: TextureLayout,
+    ) -> Result<Self, TextureError> {
+        let array = matches!(layout, TextureLayout::Array(_));
+        if !format.dds_supported() || (array && format.dxgi() == DxgiFormat::DXGI_FORMAT_UNKNOWN) {
+            return Err(TextureError::DdsUnsupported(format));
+        }
+        let mips = check_surfaces(&surfaces, format, width, height, layout)?;
+        Ok(Self {
+            header: FullDdsHeader::new(width, height, None, mips, layout, format),
+            data: surfaces,
+        })
+    }
+
+    /// Write the texture as a DDS file
+    pub fn write(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(&self.header.magic.to_le_bytes())?;
+        writer.write_all(bytemuck::bytes_of(&self.header.header))?;
+        if let Some(dx10_header) = &self.header.dx10_header {
+            writer.write_all(bytemuck::bytes_of(dx10_header))?;
+        }
+        for surface in &self.data {
+            writer.write_all(surface)?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub struct FullDdsHeader {
@@ -18,21 +99,55 @@ pub struct FullDdsHeader {
 }
 
 impl FullDdsHeader {
-    pub fn new(width: u32, height: u32, depth: Option<u32>, format: ImageFormat) -> Self {
+    /// Create a header
+    ///
+    /// Texture arrays can only be described with a DX10 header, so one is always added for them
+    /// even when the format has a legacy DDS pixel format.
+    pub fn new(
+        width: u32,
+        height: u32,
+        depth: Option<u32>,
+        mip_levels: u32,
+        layout: TextureLayout,
+        format: ImageFormat,
+    ) -> Self {
+        let mut header = DdsHeader::new(width, height, depth, mip_levels, format);
+        if layout == TextureLayout::Cubemap {
+            header.caps |= DdsCaps::COMPLEX;
+            header.caps2 = DdsCaps2::CUBEMAP | DdsCaps2::CUBEMAP_ALL_FACES;
+        }
+
+        let array = matches!(layout, TextureLayout::Array(_));
+        let dx10_header = if format.dx10() || array {
+            header.spf = PixelFormat {
+                size: 32,
+                flags: PixelFormatFlags::FOURCC,
+                fourcc: DX10_FOURCC,
+                rgb_bit_count: 0,
+                bit_mask: RgbaBitMask::zeroed(),
+            };
+            Some(Dx10Header {
+                format: format.dxgi(),
+                dimension: ResourceDimension::Texture2D,
+                flag: match layout {
+                    TextureLayout::Cubemap => RESOURCE_MISC_TEXTURECUBE,
+                    _ => 0,
+                },
+                // cubemaps count whole cubes rather than faces
+                array_size: match layout {
+                    TextureLayout::Array(layers) => layers,
+                    _ => 1,
+                },
+                flags2: 0,
+            })
+        } else {
+            None
+        };
+
         FullDdsHeader {
             magic: DDS_MAGIC,
-            header: DdsHeader::new(width, height, depth, format),
-            dx10_header: if format.dx10() {
-                Some(Dx10Header {
-                    format: format.dxgi(),
-                    dimension: ResourceDimension::Unknown,
-                    flag: 0,
-                    array_size: 0,
-                    flags2: 0,
-                })
-            } else {
-                None
-            },
+            header,
+            dx10_header,
         }
     }
 }
@@ -59,23 +174,42 @@ pub struct DdsHeader {
     pub mip_levels: u32,
     pub reserved: [u32; 11],
     pub spf: PixelFormat,
-    pub caps: u32,
-    pub caps2: u32,
+    pub caps: DdsCaps,
+    pub caps2: DdsCaps2,
     pub caps3: u32,
     pub caps4: u32,
     pub reserved2: u32,
 }
 
 impl DdsHeader {
-    pub fn new(mut width: u32, mut height: u32, depth: Option<u32>, format: ImageFormat) -> Self {
+    /// Create a header
+    ///
+    /// * `mip_levels`: Number of mip levels stored for each surface, including the full size level
+    pub fn new(
+        width: u32,
+        height: u32,
+        depth: Option<u32>,
+        mip_levels: u32,
+        format: ImageFormat,
+    ) -> Self {
         let mut flags = DdsFlags::default();
         if depth.is_some() {
-            flags = flags | DdsFlags::DEPTH;
+            flags |= DdsFlags::DEPTH;
         }
 
-        if format.is_compressed() {
-            width = (width / 4) * 4;
-            height = (height / 4) * 4;
+        // compressed textures store the size of the top level instead of the scan line size
+        let pitch = if format.is_compressed() {
+            flags |= DdsFlags::LINEAR_SIZE;
+            format.surface_size(width, height) as u32
+        } else {
+            flags |= DdsFlags::PITCH;
+            format.pitch(width)
+        };
+
+        let mut caps = DdsCaps::TEXTURE;
+        if mip_levels > 1 {
+            flags |= DdsFlags::MIPMAP_COUNT;
+            caps |= DdsCaps::COMPLEX | DdsCaps::MIPMAP;
         }
 
         Self {
@@ -83,9 +217,9 @@ impl DdsHeader {
             flags,
             height,
             width,
-            pitch: format.pitch(width),
+            pitch,
             depth: depth.unwrap_or(0),
-            mip_levels: 0,
+            mip_levels: if mip_levels > 1 { mip_levels } else { 0 },
             reserved: [0; 11],
             spf: PixelFormat {
                 size: 32,
@@ -94,8 +228,8 @@ impl DdsHeader {
                 rgb_bit_count: format.bits_per_pixel(),
                 bit_mask: format.dds_bit_mask(),
             },
-            caps: 0x1000,
-            caps2: 0,
+            caps,
+            caps2: DdsCaps2::empty(),
             caps3: 0,
             caps4: 0,
             reserved2: 0,
@@ -113,7 +247,7 @@ pub struct PixelFormat {
     pub bit_mask: RgbaBitMask,
 }
 
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
 #[repr(C)]
 pub struct RgbaBitMask {
     pub r: u32,
@@ -164,6 +298,45 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(Debug, Copy, Clone, Pod, Zeroable)]
+    #[repr(C)]
+    pub struct DdsCaps: u32 {
+        /// Optional; must be used on any file that contains more than one surface (a mipmap, a cubic environment map,
+        /// or mipmapped volume texture).
+        const COMPLEX = 0x8;
+        /// Required
+        const TEXTURE = 0x1000;
+        /// Optional; should be used for a mipmap.
+        const MIPMAP = 0x400000;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Copy, Clone, Pod, Zeroable)]
+    #[repr(C)]
+    pub struct DdsCaps2: u32 {
+        /// Required for a cube map.
+        const CUBEMAP = 0x200;
+        /// Required when these surfaces are stored in a cube map.
+        const CUBEMAP_POSITIVE_X = 0x400;
+        /// Required when these surfaces are stored in a cube map.
+        const CUBEMAP_NEGATIVE_X = 0x800;
+        /// Required when these surfaces are stored in a cube map.
+        const CUBEMAP_POSITIVE_Y = 0x1000;
+        /// Required when these surfaces are stored in a cube map.
+        const CUBEMAP_NEGATIVE_Y = 0x2000;
+        /// Required when these surfaces are stored in a cube map.
+        const CUBEMAP_POSITIVE_Z = 0x4000;
+        /// Required when these surfaces are stored in a cube map.
+        const CUBEMAP_NEGATIVE_Z = 0x8000;
+        /// Every face of a cube map.
+        const CUBEMAP_ALL_FACES = 0xFC00;
+        /// Required for a volume texture.
+        const VOLUME = 0x200000;
+    }
+}
+
 impl Default for crate::dds::DdsFlags {
     fn default() -> Self {
         Self::CAPS | Self::HEIGHT | Self::WIDTH | Self::PIXEL_FORMAT
@@ -195,7 +368,7 @@ pub mod dx10 {
     }
 
     #[repr(u32)]
-    #[derive(Debug, Copy, Clone)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub enum ResourceDimension {
         Unknown = 0,
         Buffer = 1,
@@ -207,7 +380,7 @@ pub mod dx10 {
     unsafe impl Pod for ResourceDimension {}
 
     #[repr(u32)]
-    #[derive(Debug, Copy, Clone)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     #[allow(non_camel_case_types)]
     pub enum DxgiFormat {
         DXGI_FORMAT_UNKNOWN = 0,
@@ -329,27 +502,184 @@ pub mod dx10 {
         DXGI_FORMAT_P208 = 130,
         DXGI_FORMAT_V208 = 131,
         DXGI_FORMAT_V408 = 132,
-        DXGI_FORMAT_SAMPLER_FEEDBACK_MIN_MIP_OPAQUE,
-        DXGI_FORMAT_SAMPLER_FEEDBACK_MIP_REGION_USED_OPAQUE,
+        DXGI_FORMAT_ASTC_4X4_TYPELESS = 133,
+        DXGI_FORMAT_ASTC_4X4_UNORM = 134,
+        DXGI_FORMAT_ASTC_4X4_UNORM_SRGB = 135,
+        DXGI_FORMAT_ASTC_6X6_TYPELESS = 149,
+        DXGI_FORMAT_ASTC_6X6_UNORM = 150,
+        DXGI_FORMAT_ASTC_6X6_UNORM_SRGB = 151,
+        DXGI_FORMAT_ASTC_8X8_TYPELESS = 161,
+        DXGI_FORMAT_ASTC_8X8_UNORM = 162,
+        DXGI_FORMAT_ASTC_8X8_UNORM_SRGB = 163,
+        DXGI_FORMAT_SAMPLER_FEEDBACK_MIN_MIP_OPAQUE = 189,
+        DXGI_FORMAT_SAMPLER_FEEDBACK_MIP_REGION_USED_OPAQUE = 190,
         DXGI_FORMAT_FORCE_UINT = 0xffffffff,
     }
 
     unsafe impl Zeroable for DxgiFormat {}
     unsafe impl Pod for DxgiFormat {}
+
+    impl ResourceDimension {
+        pub fn from_u32(value: u32) -> Option<Self> {
+            Some(match value {
+                0 => ResourceDimension::Unknown,
+                1 => ResourceDimension::Buffer,
+                2 => ResourceDimension::Texture1D,
+                3 => ResourceDimension::Texture2D,
+                4 => ResourceDimension::Texture3D,
+                _ => return None,
+            })
+        }
+    }
+
+    impl DxgiFormat {
+        pub fn from_u32(value: u32) -> Option<Self> {
+            match value {
+                // SAFETY: every value in these ranges is a discriminant of `DxgiFormat`
+                0..=115 | 130..=135 | 149..=151 | 161..=163 | 189 | 190 | 0xffffffff => {
+                    Some(unsafe { std::mem::transmute::<u32, DxgiFormat>(value) })
+                }
+                _ => None,
+            }
+        }
+    }
+
+    /// Value of `Dx10Header::flag` for a 2D texture that is a cube map
+    pub const RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
 }
 
 impl ImageFormat {
+    /// Number of bytes in a row of pixels (or blocks) of the given width
+    ///
+    /// Panics if the pitch doesn't fit in a `u32`, see [`ImageFormat::checked_pitch`]
     pub fn pitch(&self, width: u32) -> u32 {
+        self.checked_pitch(width).expect("surface pitch overflowed")
+    }
+
+    /// Number of bytes in a row of pixels (or blocks), `None` if it doesn't fit in a `u32`
+    pub fn checked_pitch(&self, width: u32) -> Option<u32> {
         if self.is_compressed() {
-            1.max((width + 3) / 4) * self.block_size().unwrap()
+            let (block_width, _) = self.block_footprint();
+            1.max(width.div_ceil(block_width))
+                .checked_mul(self.block_size().unwrap())
         } else {
-            (width * self.bits_per_pixel() + 7) / 8
+            Some(width.checked_mul(self.bits_per_pixel())?.div_ceil(8))
+        }
+    }
+
+    /// Number of bytes needed to store a single surface of the given size
+    ///
+    /// Panics if the size overflows, see [`ImageFormat::checked_surface_size`]
+    pub fn surface_size(&self, width: u32, height: u32) -> usize {
+        self.checked_surface_size(width, height)
+            .expect("surface size overflowed")
+    }
+
+    /// Number of bytes needed to store a single surface, `None` if the size overflows
+    ///
+    /// Readers use this for sizes taken from a file, which can't be trusted to be sensible.
+    pub fn checked_surface_size(&self, width: u32, height: u32) -> Option<usize> {
+        let rows = if self.is_compressed() {
+            let (_, block_height) = self.block_footprint();
+            1.max(height.div_ceil(block_height))
+        } else {
+            height
+        };
+        (self.checked_pitch(width)? as usize).checked_mul(rows as usize)
+    }
+
+    /// Find the format described by a DX10 header's `DxgiFormat`
+    pub fn from_dxgi(format: DxgiFormat) -> Option<Self> {
+        match format {
+            DxgiFormat::DXGI_FORMAT_R8_UNORM => Some(ImageFormat::Luma8),
+            DxgiFormat::DXGI_FORMAT_R8G8_UNORM => Some(ImageFormat::LumaAlpha8),
+            DxgiFormat::DXGI_FORMAT_R8G8B8A8_UNORM => Some(ImageFormat::Rgba8),
+            DxgiFormat::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => Some(ImageFormat::Srgba8),
+            DxgiFormat::DXGI_FORMAT_R16_UNORM => Some(ImageFormat::R16),
+            DxgiFormat::DXGI_FORMAT_R16G16_UNORM => Some(ImageFormat::Rg16),
+            DxgiFormat::DXGI_FORMAT_R16G16B16A16_UNORM => Some(ImageFormat::Rgba16),
+            DxgiFormat::DXGI_FORMAT_R16_FLOAT => Some(ImageFormat::R16F),
+            DxgiFormat::DXGI_FORMAT_R16G16B16A16_FLOAT => Some(ImageFormat::Rgba16F),
+            DxgiFormat::DXGI_FORMAT_R32G32B32A32_FLOAT => Some(ImageFormat::Rgba32F),
+            DxgiFormat::DXGI_FORMAT_BC1_UNORM => Some(ImageFormat::Rgba8_Bc1),
+            DxgiFormat::DXGI_FORMAT_BC1_UNORM_SRGB => Some(ImageFormat::Srgba8_Bc1),
+            DxgiFormat::DXGI_FORMAT_BC2_UNORM => Some(ImageFormat::Rgba8_Bc2),
+            DxgiFormat::DXGI_FORMAT_BC2_UNORM_SRGB => Some(ImageFormat::Srgba8_Bc2),
+            DxgiFormat::DXGI_FORMAT_BC3_UNORM => Some(ImageFormat::Rgba8_Bc3),
+            DxgiFormat::DXGI_FORMAT_BC3_UNORM_SRGB => Some(ImageFormat::Srgba8_Bc3),
+            DxgiFormat::DXGI_FORMAT_BC4_UNORM => Some(ImageFormat::Luma8_Bc4),
+            DxgiFormat::DXGI_FORMAT_BC4_SNORM => Some(ImageFormat::Luma8Snorm_Bc4),
+            DxgiFormat::DXGI_FORMAT_BC5_UNORM => Some(ImageFormat::Rg8_Bc5),
+            DxgiFormat::DXGI_FORMAT_BC5_SNORM => Some(ImageFormat::Rg8Snorm_Bc5),
+            DxgiFormat::DXGI_FORMAT_BC7_UNORM => Some(ImageFormat::Rgba8_Bc7),
+            DxgiFormat::DXGI_FORMAT_BC7_UNORM_SRGB => Some(ImageFormat::Srgba8_Bc7),
+            DxgiFormat::DXGI_FORMAT_BC6H_SF16 => Some(ImageFormat::RgbF_Bc6),
+            DxgiFormat::DXGI_FORMAT_BC6H_UF16 => Some(ImageFormat::RgbUF_Bc6),
+            DxgiFormat::DXGI_FORMAT_ASTC_4X4_UNORM => Some(ImageFormat::Rgba8_Astc4x4),
+            DxgiFormat::DXGI_FORMAT_ASTC_4X4_UNORM_SRGB => Some(ImageFormat::Srgba8_Astc4x4),
+            DxgiFormat::DXGI_FORMAT_ASTC_6X6_UNORM => Some(ImageFormat::Rgba8_Astc6x6),
+            DxgiFormat::DXGI_FORMAT_ASTC_6X6_UNORM_SRGB => Some(ImageFormat::Srgba8_Astc6x6),
+            DxgiFormat::DXGI_FORMAT_ASTC_8X8_UNORM => Some(ImageFormat::Rgba8_Astc8x8),
+            DxgiFormat::DXGI_FORMAT_ASTC_8X8_UNORM_SRGB => Some(ImageFormat::Srgba8_Astc8x8),
+            _ => None,
         }
     }
 
+    /// Find the format described by a legacy DDS FourCC
+    ///
+    /// Files from older tools often use these instead of a DX10 header, `ATI1` and `ATI2` are the
+    /// same as `BC4U` and `BC5U`. Wide and float formats are stored as their `D3DFMT` code.
+    pub fn from_fourcc(fourcc: u32) -> Option<Self> {
+        match fourcc {
+            DXT1_FOURCC => Some(ImageFormat::Rgba8_Bc1),
+            DXT3_FOURCC => Some(ImageFormat::Rgba8_Bc2),
+            DXT5_FOURCC => Some(ImageFormat::Rgba8_Bc3),
+            ATI1_FOURCC | BC4U_FOURCC => Some(ImageFormat::Luma8_Bc4),
+            BC4S_FOURCC => Some(ImageFormat::Luma8Snorm_Bc4),
+            ATI2_FOURCC | BC5U_FOURCC => Some(ImageFormat::Rg8_Bc5),
+            BC5S_FOURCC => Some(ImageFormat::Rg8Snorm_Bc5),
+            D3DFMT_A16B16G16R16 => Some(ImageFormat::Rgba16),
+            D3DFMT_R16F => Some(ImageFormat::R16F),
+            D3DFMT_A16B16G16R16F => Some(ImageFormat::Rgba16F),
+            D3DFMT_A32B32G32R32F => Some(ImageFormat::Rgba32F),
+            _ => None,
+        }
+    }
+
+    /// Find the uncompressed format described by a legacy DDS pixel format
+    pub fn from_pixel_format(spf: &PixelFormat) -> Option<Self> {
+        [
+            ImageFormat::Luma8,
+            ImageFormat::LumaAlpha8,
+            ImageFormat::Rgb8,
+            ImageFormat::Rgba8,
+            ImageFormat::R16,
+            ImageFormat::Rg16,
+        ]
+        .into_iter()
+        .find(|format| {
+            format.bits_per_pixel() == spf.rgb_bit_count && format.dds_bit_mask() == spf.bit_mask
+        })
+    }
+
+    /// Whether the format can be stored in a DDS file, there are no DXGI formats for Etc2 and Eac
+    pub fn dds_supported(&self) -> bool {
+        !matches!(
+            self,
+            ImageFormat::Rgb8_Etc2
+                | ImageFormat::Srgb8_Etc2
+                | ImageFormat::Rgba8_Etc2
+                | ImageFormat::Srgba8_Etc2
+                | ImageFormat::R11_Eac
+                | ImageFormat::Rg11_Eac
+        )
+    }
+
     pub fn dds_pixel_format_flags(&self) -> PixelFormatFlags {
         match self {
-            ImageFormat::Rgb8 | ImageFormat::Luma8 => PixelFormatFlags::RGB,
+            ImageFormat::Rgb8 | ImageFormat::Luma8 | ImageFormat::R16 | ImageFormat::Rg16 => {
+                PixelFormatFlags::RGB
+            }
             ImageFormat::Rgba8 | ImageFormat::LumaAlpha8 => {
                 PixelFormatFlags::RGB | PixelFormatFlags::ALPHA_PIXELS
             }
@@ -383,6 +713,18 @@ impl ImageFormat {
                 b: 0x00FF0000,
                 a: 0xFF000000,
             },
+            ImageFormat::R16 => RgbaBitMask {
+                r: 0xFFFF,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+            ImageFormat::Rg16 => RgbaBitMask {
+                r: 0x0000FFFF,
+                g: 0xFFFF0000,
+                b: 0,
+                a: 0,
+            },
             _ => RgbaBitMask {
                 r: 0,
                 g: 0,
@@ -394,30 +736,108 @@ impl ImageFormat {
 
     pub fn dx10(&self) -> bool {
         match self {
-            ImageFormat::Luma8_Bc4
+            ImageFormat::Rgba16
+            | ImageFormat::R16F
+            | ImageFormat::Rgba16F
+            | ImageFormat::Rgba32F
+            | ImageFormat::Luma8_Bc4
+            | ImageFormat::Luma8Snorm_Bc4
             | ImageFormat::LumaAlpha8_Bc5
             | ImageFormat::Rg8_Bc5
-            | ImageFormat::Rgb8_Bc7 => true,
+            | ImageFormat::Rg8Snorm_Bc5
+            | ImageFormat::Rgb8_Bc7
+            | ImageFormat::Rgba8_Bc7
+            | ImageFormat::Srgba8
+            | ImageFormat::Srgb8_Bc1
+            | ImageFormat::Srgba8_Bc1
+            | ImageFormat::Srgba8_Bc2
+            | ImageFormat::Srgba8_Bc3
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::Srgba8_Bc7
+            | ImageFormat::RgbF_Bc6
+            | ImageFormat::RgbUF_Bc6
+            | ImageFormat::Rgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Rgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Rgba8_Astc8x8
+            | ImageFormat::Srgba8_Astc8x8 => true,
             _ => false,
         }
     }
 
     pub fn dxgi(&self) -> DxgiFormat {
         match self {
+            ImageFormat::Luma8 => DxgiFormat::DXGI_FORMAT_R8_UNORM,
+            ImageFormat::LumaAlpha8 => DxgiFormat::DXGI_FORMAT_R8G8_UNORM,
+            ImageFormat::Rgba8 => DxgiFormat::DXGI_FORMAT_R8G8B8A8_UNORM,
+            ImageFormat::Srgba8 => DxgiFormat::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+            ImageFormat::R16 => DxgiFormat::DXGI_FORMAT_R16_UNORM,
+            ImageFormat::Rg16 => DxgiFormat::DXGI_FORMAT_R16G16_UNORM,
+            ImageFormat::Rgba16 => DxgiFormat::DXGI_FORMAT_R16G16B16A16_UNORM,
+            ImageFormat::R16F => DxgiFormat::DXGI_FORMAT_R16_FLOAT,
+            ImageFormat::Rgba16F => DxgiFormat::DXGI_FORMAT_R16G16B16A16_FLOAT,
+            ImageFormat::Rgba32F => DxgiFormat::DXGI_FORMAT_R32G32B32A32_FLOAT,
+            ImageFormat::Rgb8_Bc1 | ImageFormat::Rgba8_Bc1 => DxgiFormat::DXGI_FORMAT_BC1_UNORM,
+            ImageFormat::Srgb8_Bc1 | ImageFormat::Srgba8_Bc1 => {
+                DxgiFormat::DXGI_FORMAT_BC1_UNORM_SRGB
+            }
+            ImageFormat::Rgba8_Bc2 => DxgiFormat::DXGI_FORMAT_BC2_UNORM,
+            ImageFormat::Srgba8_Bc2 => DxgiFormat::DXGI_FORMAT_BC2_UNORM_SRGB,
+            ImageFormat::Rgba8_Bc3 => DxgiFormat::DXGI_FORMAT_BC3_UNORM,
+            ImageFormat::Srgba8_Bc3 => DxgiFormat::DXGI_FORMAT_BC3_UNORM_SRGB,
             ImageFormat::Luma8_Bc4 => DxgiFormat::DXGI_FORMAT_BC4_UNORM,
+            ImageFormat::Luma8Snorm_Bc4 => DxgiFormat::DXGI_FORMAT_BC4_SNORM,
             ImageFormat::LumaAlpha8_Bc5 => DxgiFormat::DXGI_FORMAT_BC5_UNORM,
             ImageFormat::Rg8_Bc5 => DxgiFormat::DXGI_FORMAT_BC5_UNORM,
-            ImageFormat::Rgb8_Bc7 => DXGI_FORMAT_BC7_UNORM,
+            ImageFormat::Rg8Snorm_Bc5 => DxgiFormat::DXGI_FORMAT_BC5_SNORM,
+            ImageFormat::Rgb8_Bc7 | ImageFormat::Rgba8_Bc7 => DXGI_FORMAT_BC7_UNORM,
+            ImageFormat::Srgb8_Bc7 | ImageFormat::Srgba8_Bc7 => {
+                DxgiFormat::DXGI_FORMAT_BC7_UNORM_SRGB
+            }
+            ImageFormat::RgbF_Bc6 => DxgiFormat::DXGI_FORMAT_BC6H_SF16,
+            ImageFormat::RgbUF_Bc6 => DxgiFormat::DXGI_FORMAT_BC6H_UF16,
+            ImageFormat::Rgba8_Astc4x4 => DxgiFormat::DXGI_FORMAT_ASTC_4X4_UNORM,
+            ImageFormat::Srgba8_Astc4x4 => DxgiFormat::DXGI_FORMAT_ASTC_4X4_UNORM_SRGB,
+            ImageFormat::Rgba8_Astc6x6 => DxgiFormat::DXGI_FORMAT_ASTC_6X6_UNORM,
+            ImageFormat::Srgba8_Astc6x6 => DxgiFormat::DXGI_FORMAT_ASTC_6X6_UNORM_SRGB,
+            ImageFormat::Rgba8_Astc8x8 => DxgiFormat::DXGI_FORMAT_ASTC_8X8_UNORM,
+            ImageFormat::Srgba8_Astc8x8 => DxgiFormat::DXGI_FORMAT_ASTC_8X8_UNORM_SRGB,
             _ => DxgiFormat::DXGI_FORMAT_UNKNOWN,
         }
     }
 
     pub fn fourcc(&self) -> u32 {
         match self {
-            ImageFormat::Luma8_Bc4
+            ImageFormat::Rgb8_Bc1 | ImageFormat::Rgba8_Bc1 => DXT1_FOURCC,
+            ImageFormat::Rgba8_Bc2 => DXT3_FOURCC,
+            ImageFormat::Rgba8_Bc3 => DXT5_FOURCC,
+            ImageFormat::Rgba16
+            | ImageFormat::R16F
+            | ImageFormat::Rgba16F
+            | ImageFormat::Rgba32F
+            | ImageFormat::Luma8_Bc4
+            | ImageFormat::Luma8Snorm_Bc4
             | ImageFormat::LumaAlpha8_Bc5
             | ImageFormat::Rg8_Bc5
-            | ImageFormat::Rgb8_Bc7 => 0x30315844,
+            | ImageFormat::Rg8Snorm_Bc5
+            | ImageFormat::Rgb8_Bc7
+            | ImageFormat::Rgba8_Bc7
+            | ImageFormat::Srgba8
+            | ImageFormat::Srgb8_Bc1
+            | ImageFormat::Srgba8_Bc1
+            | ImageFormat::Srgba8_Bc2
+            | ImageFormat::Srgba8_Bc3
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::Srgba8_Bc7
+            | ImageFormat::RgbF_Bc6
+            | ImageFormat::RgbUF_Bc6
+            | ImageFormat::Rgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Rgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Rgba8_Astc8x8
+            | ImageFormat::Srgba8_Astc8x8 => DX10_FOURCC,
             _ => 0,
         }
     }
@@ -0,0 +1,508 @@
+//! Parsing of DDS files back into headers and surfaces
+
+use crate::dds::dx10::{Dx10Header, DxgiFormat, ResourceDimension, RESOURCE_MISC_TEXTURECUBE};
+use crate::dds::{
+    DdsCaps2, DdsFlags, DdsHeader, FullDdsHeader, PixelFormat, PixelFormatFlags, RgbaBitMask,
+    DDS_MAGIC, DX10_FOURCC,
+};
+use crate::ImageFormat;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::mem;
+
+/// Errors that can occur while reading a DDS file
+#[derive(Debug)]
+pub enum DdsError {
+    Io(std::io::Error),
+    /// The file does not start with [`DDS_MAGIC`]
+    BadMagic(u32),
+    /// `DdsHeader::size` is not 124
+    BadHeaderSize(u32),
+    /// `PixelFormat::size` is not 32
+    BadPixelFormatSize(u32),
+    /// The DX10 header contains a format or dimension we don't know about
+    BadDx10Header,
+    /// The file uses a pixel format that can't be mapped to an [`ImageFormat`]
+    UnsupportedFormat,
+    /// The file ended before all of its surfaces could be read
+    UnexpectedEof {
+        expected: usize,
+        found: usize,
+    },
+    /// The header declares more mip levels than the image size allows
+    BadMipCount(u32),
+    /// The sizes in the header are too large to describe a texture
+    SizeOverflow,
+}
+
+impl Display for DdsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DdsError::Io(e) => write!(f, "failed to read dds file: {e}"),
+            DdsError::BadMagic(magic) => write!(f, "not a dds file, bad magic: {magic:#x}"),
+            DdsError::BadHeaderSize(size) => write!(f, "invalid dds header size: {size}"),
+            DdsError::BadPixelFormatSize(size) => write!(f, "invalid pixel format size: {size}"),
+            DdsError::BadDx10Header => write!(f, "invalid dx10 header"),
+            DdsError::UnsupportedFormat => write!(f, "unsupported dds pixel format"),
+            DdsError::UnexpectedEof { expected, found } => write!(
+                f,
+                "dds file is truncated, expected {expected} bytes but found {found}"
+            ),
+            DdsError::BadMipCount(mips) => write!(f, "invalid dds mip level count: {mips}"),
+            DdsError::SizeOverflow => write!(f, "dds header sizes overflowed"),
+        }
+    }
+}
+
+impl std::error::Error for DdsError {}
+
+impl From<std::io::Error> for DdsError {
+    fn from(e: std::io::Error) -> Self {
+        DdsError::Io(e)
+    }
+}
+
+/// A DDS file read back into memory
+#[derive(Debug)]
+pub struct DdsFile {
+    pub header: FullDdsHeader,
+    /// Format of the surfaces
+    ///
+    /// Uncompressed layouts without an [`ImageFormat`] of their own, like BGRA8 or RGB565, are
+    /// converted to the closest 8 bit format while reading, so this may not match `header`.
+    pub format: ImageFormat,
+    /// Every surface in the file, ordered by layer and then by mip level
+    pub surfaces: Vec<DdsSurface>,
+}
+
+/// A single mip level of one layer of a texture
+#[derive(Debug)]
+pub struct DdsSurface {
+    /// Array layer (or cube face) of this surface
+    pub layer: u32,
+    /// Mip level of this surface, 0 being the full size image
+    pub mip: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Raw surface data in `DdsFile::format`
+    pub data: Vec<u8>,
+}
+
+impl DdsFile {
+    /// Read a DDS file from a reader
+    pub fn read(mut reader: impl Read) -> Result<Self, DdsError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parse a DDS file from its raw bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DdsError> {
+        let mut data = Data::new(bytes);
+
+        let magic = data.u32()?;
+        if magic != DDS_MAGIC {
+            return Err(DdsError::BadMagic(magic));
+        }
+        let header: DdsHeader =
+            bytemuck::pod_read_unaligned(data.take(mem::size_of::<DdsHeader>())?);
+        if header.size != 124 {
+            return Err(DdsError::BadHeaderSize(header.size));
+        }
+        if header.spf.size != 32 {
+            return Err(DdsError::BadPixelFormatSize(header.spf.size));
+        }
+
+        let dx10_header = if header.spf.flags.contains(PixelFormatFlags::FOURCC)
+            && header.spf.fourcc == DX10_FOURCC
+        {
+            Some(Dx10Header {
+                format: DxgiFormat::from_u32(data.u32()?).ok_or(DdsError::BadDx10Header)?,
+                dimension: ResourceDimension::from_u32(data.u32()?)
+                    .ok_or(DdsError::BadDx10Header)?,
+                flag: data.u32()?,
+                array_size: data.u32()?,
+                flags2: data.u32()?,
+            })
+        } else {
+            None
+        };
+
+        let layout = match &dx10_header {
+            Some(dx10) => ImageFormat::from_dxgi(dx10.format)
+                .map(Layout::Format)
+                .or_else(|| BitMaskLayout::from_dxgi(dx10.format).map(Layout::BitMask)),
+            None if header.spf.flags.contains(PixelFormatFlags::FOURCC) => {
+                ImageFormat::from_fourcc(header.spf.fourcc).map(Layout::Format)
+            }
+            None => ImageFormat::from_pixel_format(&header.spf)
+                .map(Layout::Format)
+                .or_else(|| BitMaskLayout::from_pixel_format(&header.spf).map(Layout::BitMask)),
+        }
+        .ok_or(DdsError::UnsupportedFormat)?;
+        let format = layout.format();
+
+        // figure out how many surfaces are stored in the file
+        let faces = match &dx10_header {
+            Some(dx10) if dx10.flag & RESOURCE_MISC_TEXTURECUBE != 0 => 6,
+            None if header.caps2.contains(DdsCaps2::CUBEMAP) => (header.caps2
+                & DdsCaps2::CUBEMAP_ALL_FACES)
+                .bits()
+                .count_ones(),
+            _ => 1,
+        };
+        let layers = dx10_header
+            .map_or(1, |dx10| dx10.array_size.max(1))
+            .checked_mul(faces)
+            .ok_or(DdsError::SizeOverflow)?;
+        let mips = if header.flags.contains(DdsFlags::MIPMAP_COUNT) {
+            header.mip_levels.max(1)
+        } else {
+            1
+        };
+        let depth = if header.caps2.contains(DdsCaps2::VOLUME) {
+            header.depth.max(1)
+        } else {
+            1
+        };
+        // the last level of a full mip chain is 1x1, there can't be any more after it
+        let largest = header.width.max(header.height).max(depth);
+        if mips > 32 - largest.leading_zeros() {
+            return Err(DdsError::BadMipCount(mips));
+        }
+
+        let mut surfaces = vec![];
+        for layer in 0..layers {
+            for mip in 0..mips {
+                let width = (header.width >> mip).max(1);
+                let height = (header.height >> mip).max(1);
+                let depth = (depth >> mip).max(1);
+                let size = layout
+                    .surface_size(width, height)
+                    .and_then(|size| size.checked_mul(depth as usize))
+                    .ok_or(DdsError::SizeOverflow)?;
+                surfaces.push(DdsSurface {
+                    layer,
+                    mip,
+                    width,
+                    height,
+                    data: layout.unpack(data.take(size)?),
+                });
+            }
+        }
+
+        Ok(DdsFile {
+            header: FullDdsHeader {
+                magic,
+                header,
+                dx10_header,
+            },
+            format,
+            surfaces,
+        })
+    }
+
+    /// Find the surface for a given layer and mip level
+    pub fn surface(&self, layer: u32, mip: u32) -> Option<&DdsSurface> {
+        self.surfaces
+            .iter()
+            .find(|surface| surface.layer == layer && surface.mip == mip)
+    }
+}
+
+/// How the surfaces of a file are stored
+enum Layout {
+    /// Surfaces are already stored in an [`ImageFormat`]
+    Format(ImageFormat),
+    /// Surfaces need to be unpacked from their bit masks
+    BitMask(BitMaskLayout),
+}
+
+impl Layout {
+    /// Format of the surfaces once they have been read
+    fn format(&self) -> ImageFormat {
+        match self {
+            Layout::Format(format) => *format,
+            Layout::BitMask(layout) => layout.format,
+        }
+    }
+
+    /// Number of bytes a surface takes up in the file, `None` if it overflows
+    fn surface_size(&self, width: u32, height: u32) -> Option<usize> {
+        match self {
+            Layout::Format(format) => format.checked_surface_size(width, height),
+            Layout::BitMask(layout) => layout
+                .bytes_per_pixel
+                .checked_mul(width as usize)?
+                .checked_mul(height as usize),
+        }
+    }
+
+    /// Convert the bytes of a surface to [`Layout::format`]
+    fn unpack(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Layout::Format(_) => bytes.to_vec(),
+            Layout::BitMask(layout) => layout.unpack(bytes),
+        }
+    }
+}
+
+/// An uncompressed layout described by bit masks, like BGRA8 or RGB565
+struct BitMaskLayout {
+    bytes_per_pixel: usize,
+    /// Masks of each channel, luminance is stored in the red mask
+    bit_mask: RgbaBitMask,
+    /// Format the pixels are unpacked to
+    format: ImageFormat,
+}
+
+impl BitMaskLayout {
+    fn new(bit_count: u32, bit_mask: RgbaBitMask, luminance: bool, srgb: bool) -> Option<Self> {
+        if !matches!(bit_count, 8 | 16 | 24 | 32) {
+            return None;
+        }
+        let alpha = bit_mask.a != 0;
+        let format = match (luminance, alpha, srgb) {
+            (true, false, _) => ImageFormat::Luma8,
+            (true, true, _) => ImageFormat::LumaAlpha8,
+            (false, false, false) => ImageFormat::Rgb8,
+            (false, true, false) => ImageFormat::Rgba8,
+            // there is no srgb format without alpha, it is filled in as opaque instead
+            (false, _, true) => ImageFormat::Srgba8,
+        };
+        Some(Self {
+            bytes_per_pixel: bit_count as usize / 8,
+            bit_mask,
+            format,
+        })
+    }
+
+    /// Layout of a legacy pixel format that isn't stored as an [`ImageFormat`]
+    fn from_pixel_format(spf: &PixelFormat) -> Option<Self> {
+        let alpha = spf
+            .flags
+            .intersects(PixelFormatFlags::ALPHA_PIXELS | PixelFormatFlags::ALPHA);
+        let color = spf
+            .flags
+            .intersects(PixelFormatFlags::RGB | PixelFormatFlags::LUMINANCE);
+        if !alpha && !color {
+            return None;
+        }
+        let bit_mask = RgbaBitMask {
+            a: if alpha { spf.bit_mask.a } else { 0 },
+            ..spf.bit_mask
+        };
+        let luminance = spf.flags.contains(PixelFormatFlags::LUMINANCE);
+        Self::new(spf.rgb_bit_count, bit_mask, luminance, false)
+    }
+
+    /// Layout of the packed bgr(a) DXGI formats
+    fn from_dxgi(format: DxgiFormat) -> Option<Self> {
+        let mask = |r, g, b, a| RgbaBitMask { r, g, b, a };
+        let (bit_count, bit_mask, srgb) = match format {
+            DxgiFormat::DXGI_FORMAT_B5G6R5_UNORM => (16, mask(0xF800, 0x07E0, 0x001F, 0), false),
+            DxgiFormat::DXGI_FORMAT_B5G5R5A1_UNORM => {
+                (16, mask(0x7C00, 0x03E0, 0x001F, 0x8000), false)
+            }
+            DxgiFormat::DXGI_FORMAT_B4G4R4A4_UNORM => {
+                (16, mask(0x0F00, 0x00F0, 0x000F, 0xF000), false)
+            }
+            DxgiFormat::DXGI_FORMAT_B8G8R8A8_UNORM => (32, BGRA8, false),
+            DxgiFormat::DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => (32, BGRA8, true),
+            DxgiFormat::DXGI_FORMAT_B8G8R8X8_UNORM => (32, RgbaBitMask { a: 0, ..BGRA8 }, false),
+            DxgiFormat::DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+                (32, RgbaBitMask { a: 0, ..BGRA8 }, true)
+            }
+            _ => return None,
+        };
+        Self::new(bit_count, bit_mask, false, srgb)
+    }
+
+    /// Unpack every pixel of a surface to [`BitMaskLayout::format`]
+    fn unpack(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(bytes.len() / self.bytes_per_pixel * 4);
+        for pixel in bytes.chunks_exact(self.bytes_per_pixel) {
+            let mut value = [0u8; 4];
+            value[..pixel.len()].copy_from_slice(pixel);
+            let value = u32::from_le_bytes(value);
+            let [r, g, b, a] = [
+                self.bit_mask.r,
+                self.bit_mask.g,
+                self.bit_mask.b,
+                self.bit_mask.a,
+            ]
+            .map(|mask| expand(value, mask));
+            let (r, g, b, a) = (
+                r.unwrap_or(0),
+                g.unwrap_or(0),
+                b.unwrap_or(0),
+                a.unwrap_or(255),
+            );
+            match self.format {
+                ImageFormat::Luma8 => out.push(r),
+                ImageFormat::LumaAlpha8 => out.extend([r, a]),
+                ImageFormat::Rgb8 => out.extend([r, g, b]),
+                _ => out.extend([r, g, b, a]),
+            }
+        }
+        out
+    }
+}
+
+/// Masks of the common BGRA8 layout, which is stored in bgra byte order
+const BGRA8: RgbaBitMask = RgbaBitMask {
+    r: 0x00FF0000,
+    g: 0x0000FF00,
+    b: 0x000000FF,
+    a: 0xFF000000,
+};
+
+/// Extract the channel under `mask` from a pixel and scale it to 8 bits
+///
+/// returns `None` if the mask is empty
+fn expand(pixel: u32, mask: u32) -> Option<u8> {
+    if mask == 0 {
+        return None;
+    }
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let value = ((pixel & mask) >> shift) as u64;
+    Some(((value * 255 + max / 2) / max) as u8)
+}
+
+/// Cursor over the bytes of a DDS file
+struct Data<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Data<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DdsError> {
+        let end = self.offset.checked_add(len).ok_or(DdsError::SizeOverflow)?;
+        let slice = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or(DdsError::UnexpectedEof {
+                expected: end,
+                found: self.bytes.len(),
+            })?;
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, DdsError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mips::mip_size;
+    use crate::texture::{Texture, TextureBuilder};
+    use crate::TextureLayout;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    /// Encode a texture with a different gradient in every layer
+    fn texture(format: ImageFormat, layout: TextureLayout, size: u32, mips: u32) -> Texture {
+        let images = (0..layout.layers()).map(|layer| {
+            DynamicImage::ImageRgba8(RgbaImage::from_fn(size, size, |x, y| {
+                Rgba([
+                    (x * 255 / size) as u8,
+                    (y * 255 / size) as u8,
+                    (layer * 40) as u8,
+                    255 - (x * y) as u8,
+                ])
+            }))
+        });
+        TextureBuilder::new(format)
+            .images(images)
+            .layout(layout)
+            .mips(mips)
+            .build()
+            .unwrap()
+    }
+
+    fn write(texture: &Texture) -> Vec<u8> {
+        let mut bytes = vec![];
+        texture.write_dds(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Write a texture, read it back and check that every surface survived
+    fn round_trip(texture: &Texture) {
+        let file = DdsFile::from_bytes(&write(texture)).unwrap();
+        assert_eq!(file.format, texture.format);
+        assert_eq!(file.surfaces.len(), texture.surfaces.len());
+        for surface in &file.surfaces {
+            assert_eq!(
+                (surface.width, surface.height),
+                mip_size(texture.width, texture.height, surface.mip)
+            );
+            assert_eq!(
+                Some(surface.data.as_slice()),
+                texture.surface(surface.layer, surface.mip)
+            );
+        }
+    }
+
+    #[test]
+    fn mip_chain_round_trip() {
+        round_trip(&texture(
+            ImageFormat::Rgba8,
+            TextureLayout::Texture2D,
+            16,
+            u32::MAX,
+        ));
+        round_trip(&texture(
+            ImageFormat::Rgba8_Bc3,
+            TextureLayout::Texture2D,
+            16,
+            u32::MAX,
+        ));
+    }
+
+    #[test]
+    fn cubemap_round_trip() {
+        round_trip(&texture(ImageFormat::Rgba8, TextureLayout::Cubemap, 8, 2));
+        round_trip(&texture(
+            ImageFormat::Rgba8_Bc7,
+            TextureLayout::Cubemap,
+            8,
+            2,
+        ));
+    }
+
+    #[test]
+    fn array_round_trip() {
+        round_trip(&texture(
+            ImageFormat::Luma8_Bc4,
+            TextureLayout::Array(3),
+            8,
+            2,
+        ));
+    }
+
+    #[test]
+    fn truncated_file() {
+        let bytes = write(&texture(ImageFormat::Rgba8, TextureLayout::Texture2D, 4, 1));
+        let result = DdsFile::from_bytes(&bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(DdsError::UnexpectedEof { .. })));
+    }
+
+    #[test]
+    fn oversized_mip_count() {
+        let mut bytes = write(&texture(ImageFormat::Rgba8, TextureLayout::Texture2D, 1, 1));
+        let range = 4..4 + mem::size_of::<DdsHeader>();
+        let mut header: DdsHeader = bytemuck::pod_read_unaligned(&bytes[range.clone()]);
+        header.flags |= DdsFlags::MIPMAP_COUNT;
+        header.mip_levels = 40;
+        bytes[range].copy_from_slice(bytemuck::bytes_of(&header));
+        let result = DdsFile::from_bytes(&bytes);
+        assert!(matches!(result, Err(DdsError::BadMipCount(40))));
+    }
+}
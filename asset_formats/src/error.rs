@@ -0,0 +1,93 @@
+//! Error type shared by every fallible API of the crate
+
+use crate::dds::DdsError;
+use crate::ktx2::Ktx2Error;
+use crate::texture::TextureError;
+use std::fmt::{Display, Formatter};
+
+/// Any error produced by `asset_formats`
+///
+/// The container readers and [`texture`](crate::texture) keep their own error types with the
+/// details of what went wrong, they all convert into this one.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// An input image could not be opened or decoded
+    Image(image::ImageError),
+    Dds(DdsError),
+    Ktx2(Ktx2Error),
+    Texture(TextureError),
+    /// The footprint isn't one of [`astc::FOOTPRINTS`](crate::astc::FOOTPRINTS)
+    InvalidFootprint(u32, u32),
+    /// Images compared by [`metrics`](crate::metrics) aren't the same size
+    MismatchedSize {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+}
+
+/// Result of the fallible APIs of the crate
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Image(e) => write!(f, "{e}"),
+            Error::Dds(e) => write!(f, "{e}"),
+            Error::Ktx2(e) => write!(f, "{e}"),
+            Error::Texture(e) => write!(f, "{e}"),
+            Error::InvalidFootprint(width, height) => {
+                write!(f, "{width}x{height} is not an Astc block footprint")
+            }
+            Error::MismatchedSize { expected, found } => write!(
+                f,
+                "compared images must be the same size, expected {}x{} but got {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::Dds(e) => Some(e),
+            Error::Ktx2(e) => Some(e),
+            Error::Texture(e) => Some(e),
+            Error::InvalidFootprint(..) | Error::MismatchedSize { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::Image(e)
+    }
+}
+
+impl From<DdsError> for Error {
+    fn from(e: DdsError) -> Self {
+        Error::Dds(e)
+    }
+}
+
+impl From<Ktx2Error> for Error {
+    fn from(e: Ktx2Error) -> Self {
+        Error::Ktx2(e)
+    }
+}
+
+impl From<TextureError> for Error {
+    fn from(e: TextureError) -> Self {
+        Error::Texture(e)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::bcn::util::{map_blocks, put_block};
+use bytemuck::{Pod, Zeroable};
+use image::{DynamicImage, ImageBuffer, Luma, Rgb};
+
+/// Modifiers of every Eac table, scaled by the multiplier of a block
+const MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Eac compression block
+///
+/// An 8 bit base value, a 4 bit multiplier and a 4 bit modifier table followed by a 3 bit modifier
+/// index for every texel. Blocks are stored big-endian with their texels in column-major order.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct EacBlock {
+    data: [u8; 8],
+}
+
+/// Two Eac blocks holding the red and green channels of a texel block
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Rg11Block {
+    r: EacBlock,
+    g: EacBlock,
+}
+
+/// How the values of a block are reconstructed from its base value and modifiers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Precision {
+    /// 8 bit values, used for the alpha of Etc2 rgba blocks
+    Alpha8,
+    /// 11 bit values of R11 and Rg11 blocks
+    Unsigned11,
+}
+
+impl Precision {
+    /// Decode a single value of a block
+    fn value(&self, base: i32, multiplier: i32, modifier: i32) -> i32 {
+        match self {
+            Precision::Alpha8 => (base + modifier * multiplier).clamp(0, 255),
+            // a multiplier of 0 keeps the modifiers unscaled for more precision
+            Precision::Unsigned11 if multiplier == 0 => (base * 8 + 4 + modifier).clamp(0, 2047),
+            Precision::Unsigned11 => (base * 8 + 4 + modifier * multiplier * 8).clamp(0, 2047),
+        }
+    }
+}
+
+/// Encode the luma of an image as `EAC_R11_UNORM`
+pub fn encode_r11(img: &DynamicImage) -> Vec<EacBlock> {
+    let img = img.to_luma16();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        gen_block(block.map(|p| to_11(p[0])), Precision::Unsigned11)
+    })
+}
+
+/// Encode the red and green channels of an image as `EAC_R11G11_UNORM`
+pub fn encode_rg11(img: &DynamicImage) -> Vec<Rg11Block> {
+    let img = img.to_rgb16();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| Rg11Block {
+        r: gen_block(block.map(|p| to_11(p[0])), Precision::Unsigned11),
+        g: gen_block(block.map(|p| to_11(p[1])), Precision::Unsigned11),
+    })
+}
+
+/// Decode blocks generated by [`encode_r11`] into a 16 bit grayscale image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_r11(blocks: &[EacBlock], width: u32, height: u32) -> DynamicImage {
+    let mut img = ImageBuffer::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_block(block, Precision::Unsigned11).map(|v| Luma([from_11(v)]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageLuma16(img)
+}
+
+/// Decode blocks generated by [`encode_rg11`] into a 16 bit rgb image
+///
+/// The blue channel is not stored in the block so it is always zero.
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_rg11(blocks: &[Rg11Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = ImageBuffer::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let red = decode_block(&block.r, Precision::Unsigned11);
+        let green = decode_block(&block.g, Precision::Unsigned11);
+        let texels = std::array::from_fn(|px| Rgb([from_11(red[px]), from_11(green[px]), 0]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageRgb16(img)
+}
+
+/// Generate the alpha block of an Etc2 rgba block
+pub(super) fn gen_alpha_block(alpha: [u8; 16]) -> EacBlock {
+    gen_block(alpha.map(|a| a as i32), Precision::Alpha8)
+}
+
+/// Decode the alpha block of an Etc2 rgba block into its 16 values
+pub(super) fn decode_alpha_block(block: &EacBlock) -> [u8; 16] {
+    decode_block(block, Precision::Alpha8).map(|a| a as u8)
+}
+
+/// Decode a single block into its 16 values in row-major order
+fn decode_block(block: &EacBlock, precision: Precision) -> [i32; 16] {
+    let bits = u64::from_be_bytes(block.data);
+    let base = (bits >> 56) as i32;
+    let multiplier = (bits >> 52 & 0xF) as i32;
+    let table = &MODIFIERS[(bits >> 48 & 0xF) as usize];
+    std::array::from_fn(|px| {
+        let index = (bits >> index_shift(px)) & 0x7;
+        precision.value(base, multiplier, table[index as usize])
+    })
+}
+
+/// Generate block
+///
+/// Every table is tried with the multipliers around the one that spans the range of the block,
+/// centering the table on the block's values.
+///
+/// * `values`: Values of the block in row-major order, within the range of `precision`
+fn gen_block(values: [i32; 16], precision: Precision) -> EacBlock {
+    let min = *values.iter().min().unwrap();
+    let max = *values.iter().max().unwrap();
+    // 11 bit blocks scale their modifiers by 8 on top of the multiplier
+    let scale = match precision {
+        Precision::Alpha8 => 1.0,
+        Precision::Unsigned11 => 8.0,
+    };
+    let lowest_multiplier = match precision {
+        Precision::Alpha8 => 1,
+        Precision::Unsigned11 => 0,
+    };
+
+    let mut best = (u32::MAX, 0u64);
+    for (t, table) in MODIFIERS.iter().enumerate() {
+        let (low, high) = (table[3], table[7]);
+        let span = (max - min) as f32 / ((high - low) as f32 * scale);
+        let multipliers = (span.floor() as i32 - 1).clamp(lowest_multiplier, 15)
+            ..=(span.ceil() as i32 + 1).min(15);
+        for multiplier in multipliers {
+            // center of the table after scaling, relative to the base value
+            let center = match (precision, multiplier) {
+                (Precision::Unsigned11, 0) => (low + high) as f32 / 2.0 + 4.0,
+                (Precision::Unsigned11, _) => (low + high) as f32 * multiplier as f32 * 4.0 + 4.0,
+                (Precision::Alpha8, _) => (low + high) as f32 * multiplier as f32 / 2.0,
+            };
+            let base = ((((min + max) as f32 / 2.0 - center) / scale).round() as i32).clamp(0, 255);
+            for base in (base - 1..=base + 1).filter(|b| (0..=255).contains(b)) {
+                let decoded = table.map(|m| precision.value(base, multiplier, m));
+                let mut error = 0;
+                let mut indices = 0u64;
+                for (px, value) in values.iter().enumerate() {
+                    let (index, err) = decoded
+                        .iter()
+                        .map(|d| (d - value).pow(2) as u32)
+                        .enumerate()
+                        .min_by_key(|(_, err)| *err)
+                        .unwrap();
+                    error += err;
+                    indices |= (index as u64) << index_shift(px);
+                }
+                if error < best.0 {
+                    let header = (base as u64) << 56 | (multiplier as u64) << 52 | (t as u64) << 48;
+                    best = (error, header | indices);
+                }
+                if best.0 == 0 {
+                    return EacBlock {
+                        data: best.1.to_be_bytes(),
+                    };
+                }
+            }
+        }
+    }
+    EacBlock {
+        data: best.1.to_be_bytes(),
+    }
+}
+
+/// Bit offset of the modifier index of a texel, texels are stored column-major from the highest bits
+fn index_shift(px: usize) -> usize {
+    let (x, y) = (px % 4, px / 4);
+    45 - 3 * (x * 4 + y)
+}
+
+/// Scale a 16 bit value down to 11 bits
+fn to_11(value: u16) -> i32 {
+    ((value as u32 * 2047 + 32767) / 65535) as i32
+}
+
+/// Scale an 11 bit value up to 16 bits
+fn from_11(value: i32) -> u16 {
+    ((value as u32 * 65535 + 1023) / 2047) as u16
+}
@@ -0,0 +1,631 @@
+use crate::bcn::util::{channel_tables, map_blocks, put_block, texel_error, ChannelTables};
+use crate::color::ColorSpace;
+use crate::etc::eac::{self, EacBlock};
+use bytemuck::{Pod, Zeroable};
+use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+/// Small and large intensity modifiers of each table used by the individual and differential modes
+const INTENSITY: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distances between the paint colors of the T and H modes
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// Etc2 rgb compression block
+///
+/// A block is either split into two 2x4 or 4x2 halves with a base color and intensity table each
+/// (the individual and differential modes shared with Etc1), split into two groups of paint colors
+/// (the T and H modes) or covered by a gradient (the planar mode). The modes Etc1 doesn't have are
+/// stored as differential blocks whose second base color overflows. Blocks are stored big-endian
+/// with their texel indices in column-major order.
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Etc2Block {
+    data: [u8; 8],
+}
+
+/// Etc2 rgba compression block, an Eac alpha block followed by an [`Etc2Block`]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Etc2RgbaBlock {
+    alpha: EacBlock,
+    color: Etc2Block,
+}
+
+/// Encode an opaque image, the alpha channel is ignored
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode(img: &DynamicImage, color_space: ColorSpace) -> Vec<Etc2Block> {
+    let tables = channel_tables(color_space);
+    let img = img.to_rgba8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| gen_block(&block.map(|p| p.0), &tables))
+}
+
+/// Encode an rgba image, alpha is stored in its own Eac block
+///
+/// * `color_space`: Color space of the image, block errors are always measured on linear values
+pub fn encode_alpha(img: &DynamicImage, color_space: ColorSpace) -> Vec<Etc2RgbaBlock> {
+    let tables = channel_tables(color_space);
+    let img = img.to_rgba8();
+    // partial blocks are padded with the edge texels
+    map_blocks(&img, |block| {
+        let texels = block.map(|p| p.0);
+        Etc2RgbaBlock {
+            alpha: eac::gen_alpha_block(texels.map(|t| t[3])),
+            color: gen_block(&texels, &tables),
+        }
+    })
+}
+
+/// Decode blocks generated by [`encode`] into an rgb image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode(blocks: &[Etc2Block], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let texels = decode_block(u64::from_be_bytes(block.data)).map(|t| Rgb([t[0], t[1], t[2]]));
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels);
+    }
+    DynamicImage::ImageRgb8(img)
+}
+
+/// Decode blocks generated by [`encode_alpha`] into an rgba image
+///
+/// * `blocks`: Blocks of the surface in row-major order
+/// * `width`: Width of the surface in pixels
+/// * `height`: Height of the surface in pixels
+pub fn decode_alpha(blocks: &[Etc2RgbaBlock], width: u32, height: u32) -> DynamicImage {
+    let mut img = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in blocks.iter().enumerate() {
+        let alpha = eac::decode_alpha_block(&block.alpha);
+        let mut texels = decode_block(u64::from_be_bytes(block.color.data));
+        for (texel, alpha) in texels.iter_mut().zip(alpha) {
+            texel[3] = alpha;
+        }
+        let (x, y) = (i as u32 % blocks_wide, i as u32 / blocks_wide);
+        put_block(&mut img, x * 4, y * 4, &texels.map(Rgba));
+    }
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Decode a single block into its 16 opaque texels in row-major order
+fn decode_block(bits: u64) -> [[u8; 4]; 16] {
+    let field = |offset: u32, len: u32| (bits >> offset & ((1 << len) - 1)) as i32;
+    let index = |px: usize| {
+        let i = column_major(px);
+        (field(16 + i as u32, 1) << 1 | field(i as u32, 1)) as usize
+    };
+
+    if field(33, 1) == 0 {
+        let base1 = [field(60, 4), field(52, 4), field(44, 4)].map(extend4);
+        let base2 = [field(56, 4), field(48, 4), field(40, 4)].map(extend4);
+        return decode_halves(bits, base1, base2, index);
+    }
+
+    let base = [field(59, 5), field(51, 5), field(43, 5)];
+    let delta = [field(56, 3), field(48, 3), field(40, 3)].map(|d| (d << 29) >> 29);
+    let overflows = |c: usize| !(0..32).contains(&(base[c] + delta[c]));
+    if overflows(0) {
+        // T mode, a single paint color and three colors around the second base color
+        let base1 = [field(59, 2) << 2 | field(56, 2), field(52, 4), field(48, 4)].map(extend4);
+        let base2 = [field(44, 4), field(40, 4), field(36, 4)].map(extend4);
+        let distance = DISTANCES[(field(34, 2) << 1 | field(32, 1)) as usize];
+        let paint = [
+            offset(base1, 0),
+            offset(base2, distance),
+            offset(base2, 0),
+            offset(base2, -distance),
+        ];
+        std::array::from_fn(|px| paint[index(px)])
+    } else if overflows(1) {
+        // H mode, two colors around each base color
+        let base1 = [
+            field(59, 4),
+            field(56, 3) << 1 | field(52, 1),
+            field(51, 1) << 3 | field(47, 3),
+        ];
+        let base2 = [field(43, 4), field(39, 4), field(35, 4)];
+        let order = (pack4(base1) >= pack4(base2)) as i32;
+        let distance = DISTANCES[(field(34, 1) << 2 | field(32, 1) << 1 | order) as usize];
+        let (base1, base2) = (base1.map(extend4), base2.map(extend4));
+        let paint = [
+            offset(base1, distance),
+            offset(base1, -distance),
+            offset(base2, distance),
+            offset(base2, -distance),
+        ];
+        std::array::from_fn(|px| paint[index(px)])
+    } else if overflows(2) {
+        // planar mode, a gradient through the origin, horizontal and vertical colors
+        let origin = [
+            extend6(field(57, 6)),
+            extend7(field(56, 1) << 6 | field(49, 6)),
+            extend6(field(48, 1) << 5 | field(43, 2) << 3 | field(39, 3)),
+        ];
+        let horizontal = [
+            extend6(field(34, 5) << 1 | field(32, 1)),
+            extend7(field(25, 7)),
+            extend6(field(19, 6)),
+        ];
+        let vertical = [
+            extend6(field(13, 6)),
+            extend7(field(6, 7)),
+            extend6(field(0, 6)),
+        ];
+        std::array::from_fn(|px| {
+            let (x, y) = ((px % 4) as i32, (px / 4) as i32);
+            let c = |c: usize| {
+                let h = horizontal[c] - origin[c];
+                let v = vertical[c] - origin[c];
+                ((x * h + y * v + 4 * origin[c] + 2) >> 2).clamp(0, 255) as u8
+            };
+            [c(0), c(1), c(2), 255]
+        })
+    } else {
+        let base2 = [0, 1, 2].map(|c| base[c] + delta[c]);
+        decode_halves(bits, base.map(extend5), base2.map(extend5), index)
+    }
+}
+
+/// Decode a block of the individual or differential mode
+fn decode_halves(
+    bits: u64,
+    base1: [i32; 3],
+    base2: [i32; 3],
+    index: impl Fn(usize) -> usize,
+) -> [[u8; 4]; 16] {
+    let flip = bits >> 32 & 1 == 1;
+    let tables = [(bits >> 37 & 0x7) as usize, (bits >> 34 & 0x7) as usize];
+    std::array::from_fn(|px| {
+        let half = half_of(px, flip);
+        let base = [base1, base2][half];
+        offset(base, modifiers(tables[half])[index(px)])
+    })
+}
+
+/// Generate block
+///
+/// Every mode is tried and the one with the lowest error is kept.
+///
+/// * `texels`: Rgba texels of the block in row-major order, alpha is ignored
+/// * `tables`: Linear value of each channel of the texels
+fn gen_block(texels: &[[u8; 4]; 16], tables: &ChannelTables) -> Etc2Block {
+    let (bits, _) = [
+        gen_halves(texels, tables),
+        gen_t(texels, tables),
+        gen_h(texels, tables),
+        gen_planar(texels, tables),
+    ]
+    .into_iter()
+    .min_by_key(|(_, error)| *error)
+    .unwrap();
+    Etc2Block {
+        data: bits.to_be_bytes(),
+    }
+}
+
+/// Base color, intensity table and indices of one half of an individual or differential block
+#[derive(Clone, Copy)]
+struct HalfFit {
+    /// Base color before it is extended to 8 bits
+    color: [i32; 3],
+    table: u64,
+    /// Index of each texel of the half, in the order of [`halves`]
+    indices: [usize; 8],
+    error: u32,
+}
+
+/// Generate a block with the individual or differential mode, whichever fits best
+///
+/// The base colors of each half are picked around the average color of the half.
+///
+/// returns the bits of the block and its total squared error
+fn gen_halves(texels: &[[u8; 4]; 16], tables: &ChannelTables) -> (u64, u32) {
+    let mut best = (0, u32::MAX);
+    for flip in [false, true] {
+        let halves = halves(flip);
+
+        let individual = halves.map(|half| {
+            base_colors(texels, &half, 15)
+                .into_iter()
+                .map(|color| fit_half(texels, &half, color, false, tables))
+                .min_by_key(|fit| fit.error)
+                .unwrap()
+        });
+        let error = individual[0].error + individual[1].error;
+        if error < best.1 {
+            best = (halves_bits(flip, false, &individual, &halves), error);
+        }
+
+        // the second base color of the differential mode is stored as an offset of -4..3
+        let [fits1, fits2] = halves.map(|half| {
+            base_colors(texels, &half, 31)
+                .into_iter()
+                .map(|color| fit_half(texels, &half, color, true, tables))
+                .collect::<Vec<_>>()
+        });
+        for fit1 in &fits1 {
+            for fit2 in &fits2 {
+                let in_range = (0..3).all(|c| (-4..=3).contains(&(fit2.color[c] - fit1.color[c])));
+                let error = fit1.error + fit2.error;
+                if in_range && error < best.1 {
+                    best = (halves_bits(flip, true, &[*fit1, *fit2], &halves), error);
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Candidate base colors of a half, each channel of its average color rounded down and up
+///
+/// * `max`: Largest value of a quantized channel
+fn base_colors(texels: &[[u8; 4]; 16], half: &[usize; 8], max: i32) -> Vec<[i32; 3]> {
+    let average = [0, 1, 2].map(|c| {
+        let sum: u32 = half.iter().map(|&px| texels[px][c] as u32).sum();
+        sum as f32 / 8.0 * max as f32 / 255.0
+    });
+    let rounded = |c: usize, up: bool| {
+        let value = if up {
+            average[c].ceil()
+        } else {
+            average[c].floor()
+        };
+        (value as i32).clamp(0, max)
+    };
+    let mut colors = vec![];
+    for i in 0..8 {
+        let color = [0, 1, 2].map(|c| rounded(c, i >> c & 1 == 1));
+        if !colors.contains(&color) {
+            colors.push(color);
+        }
+    }
+    colors
+}
+
+/// Find the intensity table and indices that fit a half best with a given base color
+///
+/// * `color`: Quantized base color
+/// * `five_bit`: Whether `color` has 5 bit channels, otherwise it has 4 bit channels
+fn fit_half(
+    texels: &[[u8; 4]; 16],
+    half: &[usize; 8],
+    color: [i32; 3],
+    five_bit: bool,
+    tables: &ChannelTables,
+) -> HalfFit {
+    let base = if five_bit {
+        color.map(extend5)
+    } else {
+        color.map(extend4)
+    };
+    let mut best = HalfFit {
+        color,
+        table: 0,
+        indices: [0; 8],
+        error: u32::MAX,
+    };
+    for table in 0..8 {
+        let pal = modifiers(table).map(|m| offset(base, m));
+        let mut indices = [0; 8];
+        let mut error = 0;
+        for (i, &px) in half.iter().enumerate() {
+            let (index, err) = closest(&pal, &texels[px], tables);
+            indices[i] = index;
+            error += err;
+        }
+        if error < best.error {
+            best = HalfFit {
+                color,
+                table: table as u64,
+                indices,
+                error,
+            };
+        }
+    }
+    best
+}
+
+/// Pack the halves of an individual or differential block into its bits
+fn halves_bits(
+    flip: bool,
+    differential: bool,
+    fits: &[HalfFit; 2],
+    halves: &[[usize; 8]; 2],
+) -> u64 {
+    let [a, b] = fits.each_ref().map(|fit| fit.color.map(|c| c as u64));
+    let mut bits = if differential {
+        let delta = [0, 1, 2].map(|c| (b[c].wrapping_sub(a[c])) & 0x7);
+        a[0] << 59 | delta[0] << 56 | a[1] << 51 | delta[1] << 48 | a[2] << 43 | delta[2] << 40
+    } else {
+        a[0] << 60 | b[0] << 56 | a[1] << 52 | b[1] << 48 | a[2] << 44 | b[2] << 40
+    };
+    bits |= fits[0].table << 37 | fits[1].table << 34;
+    bits |= (differential as u64) << 33 | (flip as u64) << 32;
+    for (fit, half) in fits.iter().zip(halves) {
+        for (index, &px) in fit.indices.iter().zip(half) {
+            bits |= index_bits(px, *index);
+        }
+    }
+    bits
+}
+
+/// Generate a block with the T mode
+///
+/// The texels are split into two clusters, either of which may become the single paint color.
+///
+/// returns the bits of the block and its total squared error
+fn gen_t(texels: &[[u8; 4]; 16], tables: &ChannelTables) -> (u64, u32) {
+    let clusters = clusters(texels).map(quantize4);
+    let mut best = (0, u32::MAX);
+    for (color1, color2) in [(clusters[0], clusters[1]), (clusters[1], clusters[0])] {
+        let (base1, base2) = (color1.map(extend4), color2.map(extend4));
+        for (d, distance) in DISTANCES.iter().enumerate() {
+            let paint = [
+                offset(base1, 0),
+                offset(base2, *distance),
+                offset(base2, 0),
+                offset(base2, -distance),
+            ];
+            let (indices, error) = fit_paint(&paint, texels, tables);
+            if error < best.1 {
+                let [r1, g1, b1] = color1.map(|c| c as u64);
+                let [r2, g2, b2] = color2.map(|c| c as u64);
+                let d = d as u64;
+                let mut bits = (r1 >> 2) << 59 | (r1 & 0x3) << 56 | g1 << 52 | b1 << 48;
+                bits |= r2 << 44 | g2 << 40 | b2 << 36;
+                bits |= (d >> 1) << 34 | 1 << 33 | (d & 1) << 32 | indices;
+                // make the red channel of the differential mode overflow
+                bits |= if (r1 >> 2) + (r1 & 0x3) >= 4 {
+                    0x7 << 61
+                } else {
+                    1 << 58
+                };
+                best = (bits, error);
+            }
+        }
+    }
+    best
+}
+
+/// Generate a block with the H mode
+///
+/// The texels are split into two clusters with two paint colors around each of them. The lowest
+/// bit of the distance is implied by the order of the base colors.
+///
+/// returns the bits of the block and its total squared error
+fn gen_h(texels: &[[u8; 4]; 16], tables: &ChannelTables) -> (u64, u32) {
+    let clusters = clusters(texels).map(quantize4);
+    let mut best = (0, u32::MAX);
+    for (d, distance) in DISTANCES.iter().enumerate() {
+        let ordered = pack4(clusters[0]) >= pack4(clusters[1]);
+        let (color1, color2) = match (ordered, d & 1 == 1) {
+            (true, true) | (false, false) => (clusters[0], clusters[1]),
+            // equal colors can only store odd distances
+            _ if clusters[0] == clusters[1] => continue,
+            _ => (clusters[1], clusters[0]),
+        };
+        let (base1, base2) = (color1.map(extend4), color2.map(extend4));
+        let paint = [
+            offset(base1, *distance),
+            offset(base1, -distance),
+            offset(base2, *distance),
+            offset(base2, -distance),
+        ];
+        let (indices, error) = fit_paint(&paint, texels, tables);
+        if error < best.1 {
+            let [r1, g1, b1] = color1.map(|c| c as u64);
+            let [r2, g2, b2] = color2.map(|c| c as u64);
+            let d = d as u64;
+            let mut bits = r1 << 59 | (g1 >> 1) << 56 | (g1 & 1) << 52 | (b1 >> 3) << 51;
+            bits |= (b1 & 0x7) << 47 | r2 << 43 | g2 << 39 | b2 << 35;
+            bits |= (d >> 2) << 34 | 1 << 33 | (d >> 1 & 1) << 32 | indices;
+            // keep the red channel of the differential mode in range
+            if g1 >> 1 >= 4 {
+                bits |= 1 << 63;
+            }
+            // and make the green channel overflow
+            bits |= if ((g1 & 1) << 1 | b1 >> 3) + ((b1 & 0x7) >> 1) >= 4 {
+                0x7 << 53
+            } else {
+                1 << 50
+            };
+            best = (bits, error);
+        }
+    }
+    best
+}
+
+/// Generate a block with the planar mode
+///
+/// Each channel is fitted to a plane with least squares.
+///
+/// returns the bits of the block and its total squared error
+fn gen_planar(texels: &[[u8; 4]; 16], tables: &ChannelTables) -> (u64, u32) {
+    let planes = [0, 1, 2].map(|c| {
+        let mean = texels.iter().map(|t| t[c] as f32).sum::<f32>() / 16.0;
+        // x and y both go over 0..4, so each has a variance of 1.25 over the block
+        let (mut dx, mut dy) = (0.0, 0.0);
+        for (px, texel) in texels.iter().enumerate() {
+            let (x, y) = ((px % 4) as f32 - 1.5, (px / 4) as f32 - 1.5);
+            dx += x * (texel[c] as f32 - mean) / 20.0;
+            dy += y * (texel[c] as f32 - mean) / 20.0;
+        }
+        let origin = mean - 1.5 * dx - 1.5 * dy;
+        [origin, origin + 4.0 * dx, origin + 4.0 * dy]
+    });
+    let quantize = |v: f32, max: f32| (v * max / 255.0).round().clamp(0.0, max) as u64;
+    let [ro, rh, rv] = planes[0].map(|v| quantize(v, 63.0));
+    let [go, gh, gv] = planes[1].map(|v| quantize(v, 127.0));
+    let [bo, bh, bv] = planes[2].map(|v| quantize(v, 63.0));
+
+    let mut bits = ro << 57 | (go >> 6) << 56 | (go & 0x3F) << 49 | (bo >> 5) << 48;
+    bits |= (bo >> 3 & 0x3) << 43 | (bo & 0x7) << 39 | (rh >> 1) << 34 | 1 << 33 | (rh & 1) << 32;
+    bits |= gh << 25 | bh << 19 | rv << 13 | gv << 6 | bv;
+    // keep the red and green channels of the differential mode in range
+    if ro >> 1 & 1 == 1 {
+        bits |= 1 << 63;
+    }
+    if go >> 1 & 1 == 1 {
+        bits |= 1 << 55;
+    }
+    // and make the blue channel overflow
+    bits |= if (bo >> 3 & 0x3) + ((bo & 0x7) >> 1) >= 4 {
+        0x7 << 45
+    } else {
+        1 << 42
+    };
+
+    let error = decode_block(bits)
+        .iter()
+        .zip(texels)
+        .map(|(decoded, texel)| texel_error(decoded, texel, 0..3, tables))
+        .sum();
+    (bits, error)
+}
+
+/// Split the texels of a block into two clusters with k-means, starting from the two texels that
+/// are furthest apart
+///
+/// returns the average color of each cluster
+fn clusters(texels: &[[u8; 4]; 16]) -> [[f32; 3]; 2] {
+    let rgb = texels.map(|t| [t[0] as f32, t[1] as f32, t[2] as f32]);
+    let distance = |a: &[f32; 3], b: &[f32; 3]| (0..3).map(|c| (a[c] - b[c]).powi(2)).sum::<f32>();
+    let mut centers = [rgb[0], rgb[0]];
+    let mut furthest = 0.0;
+    for (i, a) in rgb.iter().enumerate() {
+        for b in &rgb[i + 1..] {
+            if distance(a, b) > furthest {
+                furthest = distance(a, b);
+                centers = [*a, *b];
+            }
+        }
+    }
+
+    for _ in 0..4 {
+        let mut sums = [[0.0; 3]; 2];
+        let mut counts = [0; 2];
+        for color in &rgb {
+            let cluster = (distance(color, &centers[1]) < distance(color, &centers[0])) as usize;
+            for c in 0..3 {
+                sums[cluster][c] += color[c];
+            }
+            counts[cluster] += 1;
+        }
+        for cluster in 0..2 {
+            if counts[cluster] > 0 {
+                centers[cluster] = sums[cluster].map(|s| s / counts[cluster] as f32);
+            }
+        }
+    }
+    centers
+}
+
+/// Map every texel of a block to its closest paint color
+///
+/// returns the index bits of the block and its total squared error
+fn fit_paint(paint: &[[u8; 4]; 4], texels: &[[u8; 4]; 16], tables: &ChannelTables) -> (u64, u32) {
+    let mut indices = 0;
+    let mut error = 0;
+    for (px, texel) in texels.iter().enumerate() {
+        let (index, err) = closest(paint, texel, tables);
+        indices |= index_bits(px, index);
+        error += err;
+    }
+    (indices, error)
+}
+
+/// Find the palette entry closest to a texel
+///
+/// returns the index of the entry and its squared error
+fn closest(pal: &[[u8; 4]; 4], texel: &[u8; 4], tables: &ChannelTables) -> (usize, u32) {
+    pal.iter()
+        .map(|c| texel_error(c, texel, 0..3, tables))
+        .enumerate()
+        .min_by_key(|(_, err)| *err)
+        .unwrap()
+}
+
+/// Texels of each half of a block, split into left and right halves or top and bottom when flipped
+fn halves(flip: bool) -> [[usize; 8]; 2] {
+    let mut halves = [[0; 8]; 2];
+    let mut len = [0; 2];
+    for px in 0..16 {
+        let half = half_of(px, flip);
+        halves[half][len[half]] = px;
+        len[half] += 1;
+    }
+    halves
+}
+
+fn half_of(px: usize, flip: bool) -> usize {
+    let (x, y) = (px % 4, px / 4);
+    if flip {
+        y / 2
+    } else {
+        x / 2
+    }
+}
+
+/// Modifiers selected by each 2 bit index of an intensity table
+fn modifiers(table: usize) -> [i32; 4] {
+    let [small, large] = INTENSITY[table];
+    [small, large, -small, -large]
+}
+
+/// Index of a row-major texel in the column-major order of the block
+fn column_major(px: usize) -> usize {
+    (px % 4) * 4 + px / 4
+}
+
+/// Place the 2 bit index of a texel in the index bits of a block, the most significant bit is
+/// stored 16 bits above the least significant one
+fn index_bits(px: usize, index: usize) -> u64 {
+    let i = column_major(px);
+    ((index as u64 >> 1) << (16 + i)) | ((index as u64 & 1) << i)
+}
+
+/// Add an offset to every channel of a color, clamping it to 8 bits
+fn offset(color: [i32; 3], offset: i32) -> [u8; 4] {
+    let [r, g, b] = color.map(|c| (c + offset).clamp(0, 255) as u8);
+    [r, g, b, 255]
+}
+
+fn quantize4(color: [f32; 3]) -> [i32; 3] {
+    color.map(|c| (c * 15.0 / 255.0).round().clamp(0.0, 15.0) as i32)
+}
+
+/// Pack a 4 bit color to compare the base colors of the H mode
+fn pack4(color: [i32; 3]) -> i32 {
+    color[0] << 8 | color[1] << 4 | color[2]
+}
+
+fn extend4(c: i32) -> i32 {
+    c << 4 | c
+}
+
+fn extend5(c: i32) -> i32 {
+    c << 3 | c >> 2
+}
+
+fn extend6(c: i32) -> i32 {
+    c << 2 | c >> 4
+}
+
+fn extend7(c: i32) -> i32 {
+    c << 1 | c >> 6
+}
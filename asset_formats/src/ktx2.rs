@@ -0,0 +1,557 @@
+//! Khronos KTX2 texture container
+//!
+//! Files are laid out as the identifier, a fixed header, an index of the other sections, the level
+//! index, the data format descriptor, key/value data and finally the mip levels from smallest to
+//! largest. Every level holds all of the layers and cube faces of that level.
+
+use crate::texture::{check_surfaces, TextureError};
+use crate::{ImageFormat, TextureLayout};
+use std::io::Write;
+
+mod read;
+pub use read::*;
+
+/// First 12 bytes of every KTX2 file
+pub const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Size of the identifier, header and section index that start every file
+const HEADER_SIZE: usize = 80;
+/// Size of a single entry of the level index
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// Compression applied on top of the texture data of each mip level
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Supercompression {
+    #[default]
+    None,
+    /// Zstandard with the given compression level
+    Zstd(i32),
+}
+
+impl Supercompression {
+    /// Value of the header's `supercompressionScheme` field
+    pub fn scheme(&self) -> u32 {
+        match self {
+            Supercompression::None => 0,
+            Supercompression::Zstd(_) => 2,
+        }
+    }
+}
+
+/// A KTX2 texture, either read from a file or ready to be written to one
+#[derive(Debug)]
+pub struct Ktx2File {
+    /// Format of the stored surfaces
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub layout: TextureLayout,
+    pub supercompression: Supercompression,
+    /// Every surface in the file, ordered by layer and then by mip level
+    pub surfaces: Vec<Ktx2Surface>,
+}
+
+/// A single mip level of one layer of a texture
+#[derive(Debug)]
+pub struct Ktx2Surface {
+    /// Array layer (or cube face) of this surface
+    pub layer: u32,
+    /// Mip level of this surface, 0 being the full size image
+    pub mip: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Raw surface data in `Ktx2File::format`
+    pub data: Vec<u8>,
+}
+
+impl Ktx2File {
+    /// Create a texture out of encoded surfaces
+    ///
+    /// Fails if the surfaces don't make up a whole mip chain for every layer.
+    ///
+    /// * `width`: Width of the full size surfaces
+    /// * `height`: Height of the full size surfaces
+    /// * `surfaces`: Data of every surface, ordered by layer and then by mip level
+    pub fn new(
+        format: ImageFormat,
+        width: u32,
+        height: u32,
+        layout: TextureLayout,
+        supercompression: Supercompression,
+        surfaces: Vec<Vec<u8>>,
+    ) -> Result<Self, TextureError> {
+        let mips = check_surfaces(&surfaces, format, width, height, layout)?;
+        let surfaces = surfaces
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| {
+                let (layer, mip) = (i as u32 / mips, i as u32 % mips);
+                Ktx2Surface {
+                    layer,
+                    mip,
+                    width: (width >> mip).max(1),
+                    height: (height >> mip).max(1),
+                    data,
+                }
+            })
+            .collect();
+        Ok(Self {
+            format,
+            width,
+            height,
+            layout,
+            supercompression,
+            surfaces,
+        })
+    }
+
+    /// Number of mip levels stored for each layer
+    pub fn mip_levels(&self) -> u32 {
+        self.surfaces.iter().map(|s| s.mip + 1).max().unwrap_or(1)
+    }
+
+    /// Find the surface for a given layer and mip level
+    pub fn surface(&self, layer: u32, mip: u32) -> Option<&Ktx2Surface> {
+        self.surfaces
+            .iter()
+            .find(|surface| surface.layer == layer && surface.mip == mip)
+    }
+
+    /// Write the texture as a KTX2 file
+    pub fn write(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let levels = self.mip_levels();
+        let layers = self.layout.layers();
+
+        // every level holds its layers back to back, supercompressed as a whole
+        let mut level_data = vec![];
+        for mip in 0..levels {
+            let mut data = vec![];
+            for layer in 0..layers {
+                if let Some(surface) = self.surface(layer, mip) {
+                    data.extend_from_slice(&surface.data);
+                }
+            }
+            let uncompressed_len = data.len();
+            if let Supercompression::Zstd(level) = self.supercompression {
+                data = zstd::encode_all(data.as_slice(), level)?;
+            }
+            level_data.push((data, uncompressed_len));
+        }
+
+        let dfd = data_format_descriptor(self.format);
+        let kvd = key_value_data(self.format);
+        let dfd_offset = HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE * levels as usize;
+        let kvd_offset = dfd_offset + dfd.len();
+
+        // levels are stored smallest first, uncompressed levels are aligned to a whole block
+        let alignment = match self.supercompression {
+            Supercompression::None => lcm(self.format.texel_block_size(), 4),
+            Supercompression::Zstd(_) => 1,
+        };
+        let mut offsets = vec![0; levels as usize];
+        let mut end = kvd_offset + kvd.len();
+        for mip in (0..levels as usize).rev() {
+            let offset = end.next_multiple_of(alignment);
+            offsets[mip] = offset;
+            end = offset + level_data[mip].0.len();
+        }
+
+        let mut bytes = Vec::with_capacity(end);
+        bytes.extend_from_slice(&KTX2_IDENTIFIER);
+        for value in [
+            self.format.vk_format(),
+            self.format.type_size(),
+            self.width,
+            self.height,
+            0,
+            match self.layout {
+                TextureLayout::Array(layers) => layers,
+                _ => 0,
+            },
+            match self.layout {
+                TextureLayout::Cubemap => 6,
+                _ => 1,
+            },
+            levels,
+            self.supercompression.scheme(),
+            dfd_offset as u32,
+            dfd.len() as u32,
+            kvd_offset as u32,
+            kvd.len() as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        // no supercompression global data
+        bytes.extend_from_slice(&[0; 16]);
+        for (offset, (data, uncompressed_len)) in offsets.iter().zip(&level_data) {
+            for value in [*offset, data.len(), *uncompressed_len] {
+                bytes.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&dfd);
+        bytes.extend_from_slice(&kvd);
+        for mip in (0..levels as usize).rev() {
+            bytes.resize(offsets[mip], 0);
+            bytes.extend_from_slice(&level_data[mip].0);
+        }
+        writer.write_all(&bytes)
+    }
+}
+
+/// Build the data format descriptor of a format, including its leading total size
+fn data_format_descriptor(format: ImageFormat) -> Vec<u8> {
+    let desc = format.descriptor();
+    let compressed = format.is_compressed();
+    let transfer = match format.color_space() {
+        crate::color::ColorSpace::Linear => dfd::TRANSFER_LINEAR,
+        crate::color::ColorSpace::Srgb => dfd::TRANSFER_SRGB,
+    };
+    let block_size = 24 + 16 * desc.samples.len() as u32;
+    // each byte of the texel block dimensions holds a dimension minus one
+    let (block_width, block_height) = format.block_footprint();
+
+    let mut words = vec![
+        4 + block_size,
+        // vendor and descriptor type are both 0 for a basic descriptor
+        0,
+        dfd::VERSION | (block_size << 16),
+        desc.model | (dfd::PRIMARIES_BT709 << 8) | (transfer << 16),
+        (block_width - 1) | (block_height - 1) << 8,
+        format.texel_block_size() as u32,
+        0,
+    ];
+    let mut offset = 0;
+    for (channel, bits) in desc.samples {
+        let mut channel_type = *channel;
+        // alpha is linear even when the color channels use the sRGB transfer function
+        if transfer == dfd::TRANSFER_SRGB && *channel == dfd::CHANNEL_ALPHA {
+            channel_type |= dfd::QUALIFIER_LINEAR;
+        }
+        if desc.float {
+            channel_type |= dfd::QUALIFIER_FLOAT;
+        }
+        if desc.signed {
+            channel_type |= dfd::QUALIFIER_SIGNED;
+        }
+        let (lower, upper) = match (desc.float, desc.signed) {
+            (true, true) => (0xBF800000, 0x3F800000),
+            (true, false) => (0, 0x3F800000),
+            (false, true) => (0x80000000, 0x7FFFFFFF),
+            (false, false) if compressed => (0, u32::MAX),
+            (false, false) => (0, (1u32 << bits) - 1),
+        };
+        words.push(offset | ((bits - 1) << 16) | (channel_type << 24));
+        words.push(0);
+        words.push(lower);
+        words.push(upper);
+        offset += bits;
+    }
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+/// Build the key/value data of a file
+///
+/// Formats that spread a single channel over rgb get a `KTXswizzle` entry, so loaders can tell a
+/// grayscale texture apart from a red one.
+fn key_value_data(format: ImageFormat) -> Vec<u8> {
+    let mut entries = vec![(
+        "KTXwriter",
+        concat!("asset_formats ", env!("CARGO_PKG_VERSION")),
+    )];
+    if let Some(swizzle) = format.ktx2_swizzle() {
+        entries.push(("KTXswizzle", swizzle));
+    }
+    // entries are sorted by key
+    entries.sort();
+
+    let mut bytes = vec![];
+    for (key, value) in entries {
+        let len = key.len() + value.len() + 2;
+        bytes.extend_from_slice(&(len as u32).to_le_bytes());
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+    }
+    bytes
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let gcd = |mut a: usize, mut b: usize| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    a / gcd(a, b) * b
+}
+
+/// Constants of the Khronos data format descriptor
+pub mod dfd {
+    pub const VERSION: u32 = 2;
+
+    pub const MODEL_RGBSDA: u32 = 1;
+    pub const MODEL_BC1A: u32 = 128;
+    pub const MODEL_BC2: u32 = 129;
+    pub const MODEL_BC3: u32 = 130;
+    pub const MODEL_BC4: u32 = 131;
+    pub const MODEL_BC5: u32 = 132;
+    pub const MODEL_BC6H: u32 = 133;
+    pub const MODEL_BC7: u32 = 134;
+    pub const MODEL_ETC2: u32 = 161;
+    pub const MODEL_ASTC: u32 = 162;
+
+    pub const PRIMARIES_BT709: u32 = 1;
+    pub const TRANSFER_LINEAR: u32 = 1;
+    pub const TRANSFER_SRGB: u32 = 2;
+
+    pub const CHANNEL_RED: u32 = 0;
+    pub const CHANNEL_GREEN: u32 = 1;
+    pub const CHANNEL_BLUE: u32 = 2;
+    pub const CHANNEL_ALPHA: u32 = 15;
+    /// The color channel of the block compressed models
+    pub const CHANNEL_COLOR: u32 = 0;
+    /// Bc1 blocks that can be transparent
+    pub const CHANNEL_BC1A_ALPHA_PRESENT: u32 = 1;
+    /// The color channel of the Etc2 model, its red and green channels match the rgb model's
+    pub const CHANNEL_ETC2_COLOR: u32 = 2;
+
+    pub const QUALIFIER_LINEAR: u32 = 0x10;
+    pub const QUALIFIER_SIGNED: u32 = 0x40;
+    pub const QUALIFIER_FLOAT: u32 = 0x80;
+}
+
+/// Color model and samples of a format's data format descriptor
+struct Descriptor {
+    model: u32,
+    /// Channel and bit length of each sample, in the order they are stored
+    samples: &'static [(u32, u32)],
+    float: bool,
+    signed: bool,
+}
+
+impl ImageFormat {
+    /// `VkFormat` of the format as stored in the KTX2 header
+    pub fn vk_format(&self) -> u32 {
+        match self {
+            ImageFormat::Luma8 => 9,
+            ImageFormat::LumaAlpha8 => 16,
+            ImageFormat::Rgb8 => 23,
+            ImageFormat::Rgba8 => 37,
+            ImageFormat::Srgba8 => 43,
+            ImageFormat::R16 => 70,
+            ImageFormat::R16F => 76,
+            ImageFormat::Rg16 => 77,
+            ImageFormat::Rgba16 => 91,
+            ImageFormat::Rgba16F => 97,
+            ImageFormat::Rgba32F => 109,
+            ImageFormat::Rgb8_Bc1 => 131,
+            ImageFormat::Srgb8_Bc1 => 132,
+            ImageFormat::Rgba8_Bc1 => 133,
+            ImageFormat::Srgba8_Bc1 => 134,
+            ImageFormat::Rgba8_Bc2 => 135,
+            ImageFormat::Srgba8_Bc2 => 136,
+            ImageFormat::Rgba8_Bc3 => 137,
+            ImageFormat::Srgba8_Bc3 => 138,
+            ImageFormat::Luma8_Bc4 => 139,
+            ImageFormat::Luma8Snorm_Bc4 => 140,
+            ImageFormat::LumaAlpha8_Bc5 | ImageFormat::Rg8_Bc5 => 141,
+            ImageFormat::Rg8Snorm_Bc5 => 142,
+            ImageFormat::RgbUF_Bc6 => 143,
+            ImageFormat::RgbF_Bc6 => 144,
+            ImageFormat::Rgb8_Bc7 | ImageFormat::Rgba8_Bc7 => 145,
+            ImageFormat::Srgb8_Bc7 | ImageFormat::Srgba8_Bc7 => 146,
+            ImageFormat::Rgb8_Etc2 => 147,
+            ImageFormat::Srgb8_Etc2 => 148,
+            ImageFormat::Rgba8_Etc2 => 151,
+            ImageFormat::Srgba8_Etc2 => 152,
+            ImageFormat::R11_Eac => 153,
+            ImageFormat::Rg11_Eac => 155,
+            ImageFormat::Rgba8_Astc4x4 => 157,
+            ImageFormat::Srgba8_Astc4x4 => 158,
+            ImageFormat::Rgba8_Astc6x6 => 165,
+            ImageFormat::Srgba8_Astc6x6 => 166,
+            ImageFormat::Rgba8_Astc8x8 => 171,
+            ImageFormat::Srgba8_Astc8x8 => 172,
+        }
+    }
+
+    /// Find the format described by a KTX2 `VkFormat`
+    ///
+    /// * `swizzle`: Value of the file's `KTXswizzle` key, used to tell grayscale formats apart
+    pub fn from_vk_format(vk_format: u32, swizzle: Option<&str>) -> Option<Self> {
+        let gray = matches!(swizzle, Some("rrr1") | Some("rrrg"));
+        Some(match vk_format {
+            9 => ImageFormat::Luma8,
+            16 => ImageFormat::LumaAlpha8,
+            23 => ImageFormat::Rgb8,
+            37 => ImageFormat::Rgba8,
+            43 => ImageFormat::Srgba8,
+            70 => ImageFormat::R16,
+            76 => ImageFormat::R16F,
+            77 => ImageFormat::Rg16,
+            91 => ImageFormat::Rgba16,
+            97 => ImageFormat::Rgba16F,
+            109 => ImageFormat::Rgba32F,
+            131 => ImageFormat::Rgb8_Bc1,
+            132 => ImageFormat::Srgb8_Bc1,
+            133 => ImageFormat::Rgba8_Bc1,
+            134 => ImageFormat::Srgba8_Bc1,
+            135 => ImageFormat::Rgba8_Bc2,
+            136 => ImageFormat::Srgba8_Bc2,
+            137 => ImageFormat::Rgba8_Bc3,
+            138 => ImageFormat::Srgba8_Bc3,
+            139 => ImageFormat::Luma8_Bc4,
+            140 => ImageFormat::Luma8Snorm_Bc4,
+            141 if gray => ImageFormat::LumaAlpha8_Bc5,
+            141 => ImageFormat::Rg8_Bc5,
+            142 => ImageFormat::Rg8Snorm_Bc5,
+            143 => ImageFormat::RgbUF_Bc6,
+            144 => ImageFormat::RgbF_Bc6,
+            145 => ImageFormat::Rgba8_Bc7,
+            146 => ImageFormat::Srgba8_Bc7,
+            147 => ImageFormat::Rgb8_Etc2,
+            148 => ImageFormat::Srgb8_Etc2,
+            151 => ImageFormat::Rgba8_Etc2,
+            152 => ImageFormat::Srgba8_Etc2,
+            153 => ImageFormat::R11_Eac,
+            155 => ImageFormat::Rg11_Eac,
+            157 => ImageFormat::Rgba8_Astc4x4,
+            158 => ImageFormat::Srgba8_Astc4x4,
+            165 => ImageFormat::Rgba8_Astc6x6,
+            166 => ImageFormat::Srgba8_Astc6x6,
+            171 => ImageFormat::Rgba8_Astc8x8,
+            172 => ImageFormat::Srgba8_Astc8x8,
+            _ => return None,
+        })
+    }
+
+    /// Size of the data type of a single channel, 1 for block compressed formats
+    pub fn type_size(&self) -> u32 {
+        match self {
+            ImageFormat::R16
+            | ImageFormat::Rg16
+            | ImageFormat::Rgba16
+            | ImageFormat::R16F
+            | ImageFormat::Rgba16F => 2,
+            ImageFormat::Rgba32F => 4,
+            _ => 1,
+        }
+    }
+
+    /// Bytes in a single block, or a single pixel for uncompressed formats
+    pub fn texel_block_size(&self) -> usize {
+        match self.block_size() {
+            Some(size) => size as usize,
+            None => self.bits_per_pixel() as usize / 8,
+        }
+    }
+
+    /// Value of the `KTXswizzle` key for formats that don't map their channels directly to rgba
+    pub fn ktx2_swizzle(&self) -> Option<&'static str> {
+        match self {
+            ImageFormat::Luma8
+            | ImageFormat::Luma8_Bc4
+            | ImageFormat::Luma8Snorm_Bc4
+            | ImageFormat::R11_Eac
+            | ImageFormat::R16
+            | ImageFormat::R16F => Some("rrr1"),
+            ImageFormat::LumaAlpha8 | ImageFormat::LumaAlpha8_Bc5 => Some("rrrg"),
+            _ => None,
+        }
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        use dfd::*;
+        let (model, samples): (u32, &'static [(u32, u32)]) = match self {
+            ImageFormat::Luma8 => (MODEL_RGBSDA, &[(CHANNEL_RED, 8)]),
+            ImageFormat::LumaAlpha8 => (MODEL_RGBSDA, &[(CHANNEL_RED, 8), (CHANNEL_GREEN, 8)]),
+            ImageFormat::Rgb8 => (
+                MODEL_RGBSDA,
+                &[(CHANNEL_RED, 8), (CHANNEL_GREEN, 8), (CHANNEL_BLUE, 8)],
+            ),
+            ImageFormat::Rgba8 | ImageFormat::Srgba8 => (
+                MODEL_RGBSDA,
+                &[
+                    (CHANNEL_RED, 8),
+                    (CHANNEL_GREEN, 8),
+                    (CHANNEL_BLUE, 8),
+                    (CHANNEL_ALPHA, 8),
+                ],
+            ),
+            ImageFormat::R16 | ImageFormat::R16F => (MODEL_RGBSDA, &[(CHANNEL_RED, 16)]),
+            ImageFormat::Rg16 => (MODEL_RGBSDA, &[(CHANNEL_RED, 16), (CHANNEL_GREEN, 16)]),
+            ImageFormat::Rgba16 | ImageFormat::Rgba16F => (
+                MODEL_RGBSDA,
+                &[
+                    (CHANNEL_RED, 16),
+                    (CHANNEL_GREEN, 16),
+                    (CHANNEL_BLUE, 16),
+                    (CHANNEL_ALPHA, 16),
+                ],
+            ),
+            ImageFormat::Rgba32F => (
+                MODEL_RGBSDA,
+                &[
+                    (CHANNEL_RED, 32),
+                    (CHANNEL_GREEN, 32),
+                    (CHANNEL_BLUE, 32),
+                    (CHANNEL_ALPHA, 32),
+                ],
+            ),
+            ImageFormat::Rgb8_Bc1 | ImageFormat::Srgb8_Bc1 => (MODEL_BC1A, &[(CHANNEL_COLOR, 64)]),
+            ImageFormat::Rgba8_Bc1 | ImageFormat::Srgba8_Bc1 => {
+                (MODEL_BC1A, &[(CHANNEL_BC1A_ALPHA_PRESENT, 64)])
+            }
+            ImageFormat::Rgba8_Bc2 | ImageFormat::Srgba8_Bc2 => {
+                (MODEL_BC2, &[(CHANNEL_ALPHA, 64), (CHANNEL_COLOR, 64)])
+            }
+            ImageFormat::Rgba8_Bc3 | ImageFormat::Srgba8_Bc3 => {
+                (MODEL_BC3, &[(CHANNEL_ALPHA, 64), (CHANNEL_COLOR, 64)])
+            }
+            ImageFormat::Luma8_Bc4 | ImageFormat::Luma8Snorm_Bc4 => {
+                (MODEL_BC4, &[(CHANNEL_RED, 64)])
+            }
+            ImageFormat::LumaAlpha8_Bc5 | ImageFormat::Rg8_Bc5 | ImageFormat::Rg8Snorm_Bc5 => {
+                (MODEL_BC5, &[(CHANNEL_RED, 64), (CHANNEL_GREEN, 64)])
+            }
+            ImageFormat::RgbUF_Bc6 | ImageFormat::RgbF_Bc6 => (MODEL_BC6H, &[(CHANNEL_COLOR, 128)]),
+            ImageFormat::Rgb8_Bc7
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::Rgba8_Bc7
+            | ImageFormat::Srgba8_Bc7 => (MODEL_BC7, &[(CHANNEL_COLOR, 128)]),
+            ImageFormat::Rgb8_Etc2 | ImageFormat::Srgb8_Etc2 => {
+                (MODEL_ETC2, &[(CHANNEL_ETC2_COLOR, 64)])
+            }
+            ImageFormat::Rgba8_Etc2 | ImageFormat::Srgba8_Etc2 => {
+                (MODEL_ETC2, &[(CHANNEL_ALPHA, 64), (CHANNEL_ETC2_COLOR, 64)])
+            }
+            ImageFormat::R11_Eac => (MODEL_ETC2, &[(CHANNEL_RED, 64)]),
+            ImageFormat::Rg11_Eac => (MODEL_ETC2, &[(CHANNEL_RED, 64), (CHANNEL_GREEN, 64)]),
+            ImageFormat::Rgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Rgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Rgba8_Astc8x8
+            | ImageFormat::Srgba8_Astc8x8 => (MODEL_ASTC, &[(CHANNEL_COLOR, 128)]),
+        };
+        // uncompressed floats are always signed
+        let float = matches!(
+            self,
+            ImageFormat::R16F | ImageFormat::Rgba16F | ImageFormat::Rgba32F
+        );
+        Descriptor {
+            model,
+            samples,
+            float: float || matches!(self, ImageFormat::RgbUF_Bc6 | ImageFormat::RgbF_Bc6),
+            signed: float
+                || matches!(
+                    self,
+                    ImageFormat::RgbF_Bc6 | ImageFormat::Luma8Snorm_Bc4 | ImageFormat::Rg8Snorm_Bc5
+                ),
+        }
+    }
+}
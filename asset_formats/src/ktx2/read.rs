@@ -0,0 +1,377 @@
+//! Parsing of KTX2 files back into surfaces
+
+use crate::ktx2::{Ktx2File, Ktx2Surface, Supercompression, KTX2_IDENTIFIER};
+use crate::{ImageFormat, TextureLayout};
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+/// Errors that can occur while reading a KTX2 file
+#[derive(Debug)]
+pub enum Ktx2Error {
+    Io(std::io::Error),
+    /// The file does not start with [`KTX2_IDENTIFIER`]
+    BadIdentifier,
+    /// The file uses a `VkFormat` that can't be mapped to an [`ImageFormat`]
+    UnsupportedFormat(u32),
+    /// The file uses a supercompression scheme other than none or zstd
+    UnsupportedSupercompression(u32),
+    /// The file describes a 3D texture
+    UnsupportedDepth(u32),
+    /// The file describes a cube map array, or a face count other than 1 or 6
+    UnsupportedLayout {
+        layers: u32,
+        faces: u32,
+    },
+    /// The file declares more mip levels than the image size allows
+    BadLevelCount(u32),
+    /// The sizes in the file are too large to describe a texture
+    SizeOverflow,
+    /// A level doesn't hold as many bytes as its surfaces need
+    BadLevelSize {
+        level: u32,
+        expected: usize,
+        found: usize,
+    },
+    /// The file ended before all of its sections could be read
+    UnexpectedEof {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for Ktx2Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ktx2Error::Io(e) => write!(f, "failed to read ktx2 file: {e}"),
+            Ktx2Error::BadIdentifier => write!(f, "not a ktx2 file, bad identifier"),
+            Ktx2Error::UnsupportedFormat(format) => {
+                write!(f, "unsupported ktx2 vkFormat: {format}")
+            }
+            Ktx2Error::UnsupportedSupercompression(scheme) => {
+                write!(f, "unsupported ktx2 supercompression scheme: {scheme}")
+            }
+            Ktx2Error::UnsupportedDepth(depth) => {
+                write!(f, "3D ktx2 textures are unsupported, depth: {depth}")
+            }
+            Ktx2Error::UnsupportedLayout { layers, faces } => write!(
+                f,
+                "unsupported ktx2 layout with {layers} layers and {faces} faces"
+            ),
+            Ktx2Error::BadLevelCount(levels) => write!(f, "invalid ktx2 level count: {levels}"),
+            Ktx2Error::SizeOverflow => write!(f, "ktx2 sizes overflowed"),
+            Ktx2Error::BadLevelSize {
+                level,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ktx2 level {level} should hold {expected} bytes but holds {found}"
+            ),
+            Ktx2Error::UnexpectedEof { expected, found } => write!(
+                f,
+                "ktx2 file is truncated, expected {expected} bytes but found {found}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Ktx2Error {}
+
+impl From<std::io::Error> for Ktx2Error {
+    fn from(e: std::io::Error) -> Self {
+        Ktx2Error::Io(e)
+    }
+}
+
+impl Ktx2File {
+    /// Read a KTX2 file from a reader
+    pub fn read(mut reader: impl Read) -> Result<Self, Ktx2Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parse a KTX2 file from its raw bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Ktx2Error> {
+        let mut data = Data::new(bytes);
+
+        if data.take(KTX2_IDENTIFIER.len())? != KTX2_IDENTIFIER {
+            return Err(Ktx2Error::BadIdentifier);
+        }
+        let vk_format = data.u32()?;
+        let _type_size = data.u32()?;
+        let width = data.u32()?;
+        let height = data.u32()?;
+        let depth = data.u32()?;
+        let layer_count = data.u32()?;
+        let face_count = data.u32()?;
+        let levels = data.u32()?.max(1);
+        let supercompression = match data.u32()? {
+            0 => Supercompression::None,
+            // the level isn't stored in the file, use zstd's default
+            2 => Supercompression::Zstd(0),
+            scheme => return Err(Ktx2Error::UnsupportedSupercompression(scheme)),
+        };
+        if depth > 1 {
+            return Err(Ktx2Error::UnsupportedDepth(depth));
+        }
+        // the last level of a full mip chain is 1x1, there can't be any more after it
+        if levels > 32 - width.max(height).max(1).leading_zeros() {
+            return Err(Ktx2Error::BadLevelCount(levels));
+        }
+        // the data format descriptor is implied by the format, so it is skipped
+        let _dfd = (data.u32()?, data.u32()?);
+        let kvd_offset = data.u32()? as usize;
+        let kvd_len = data.u32()? as usize;
+        let _sgd = (data.u64()?, data.u64()?);
+
+        let mut level_index = vec![];
+        for _ in 0..levels {
+            level_index.push((data.size()?, data.size()?, data.size()?));
+        }
+
+        let swizzle = key_value_data(Data::new(bytes).slice(kvd_offset, kvd_len)?)
+            .into_iter()
+            .find(|(key, _)| *key == "KTXswizzle")
+            .map(|(_, value)| value);
+        let format = ImageFormat::from_vk_format(vk_format, swizzle)
+            .ok_or(Ktx2Error::UnsupportedFormat(vk_format))?;
+
+        let layout = match (layer_count, face_count) {
+            (0, 6) => TextureLayout::Cubemap,
+            (0, 0 | 1) => TextureLayout::Texture2D,
+            (layers, 0 | 1) => TextureLayout::Array(layers),
+            (layers, faces) => return Err(Ktx2Error::UnsupportedLayout { layers, faces }),
+        };
+        let layers = layout.layers();
+
+        let mut surfaces = vec![];
+        for (mip, (offset, len, _)) in (0..levels).zip(level_index) {
+            let level = Data::new(bytes).slice(offset, len)?;
+            let level = match supercompression {
+                Supercompression::None => level.to_vec(),
+                Supercompression::Zstd(_) => zstd::decode_all(level)?,
+            };
+
+            let width = (width >> mip).max(1);
+            let height = (height >> mip).max(1);
+            let size = format
+                .checked_surface_size(width, height)
+                .ok_or(Ktx2Error::SizeOverflow)?;
+            let expected = size
+                .checked_mul(layers as usize)
+                .ok_or(Ktx2Error::SizeOverflow)?;
+            if level.len() < expected {
+                return Err(Ktx2Error::BadLevelSize {
+                    level: mip,
+                    expected,
+                    found: level.len(),
+                });
+            }
+            for (layer, data) in (0..layers).zip(level.chunks_exact(size)) {
+                surfaces.push(Ktx2Surface {
+                    layer,
+                    mip,
+                    width,
+                    height,
+                    data: data.to_vec(),
+                });
+            }
+        }
+        // levels hold every layer, but surfaces are ordered by layer first
+        surfaces.sort_by_key(|surface| (surface.layer, surface.mip));
+
+        Ok(Ktx2File {
+            format,
+            width,
+            height,
+            layout,
+            supercompression,
+            surfaces,
+        })
+    }
+}
+
+/// Split key/value data into its null terminated string entries
+fn key_value_data(mut data: &[u8]) -> Vec<(&str, &str)> {
+    let mut entries = vec![];
+    while data.len() >= 4 {
+        let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let Some(entry) = data.get(4..4 + len) else {
+            break;
+        };
+        let mut parts = entry.splitn(2, |b| *b == 0);
+        let key = parts.next().and_then(|k| std::str::from_utf8(k).ok());
+        let value = parts
+            .next()
+            .map(|v| v.strip_suffix(&[0]).unwrap_or(v))
+            .and_then(|v| std::str::from_utf8(v).ok());
+        if let (Some(key), Some(value)) = (key, value) {
+            entries.push((key, value));
+        }
+        data = data.get((4 + len).next_multiple_of(4)..).unwrap_or(&[]);
+    }
+    entries
+}
+
+/// Cursor over the bytes of a KTX2 file
+struct Data<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Data<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], Ktx2Error> {
+        let end = offset.checked_add(len).ok_or(Ktx2Error::SizeOverflow)?;
+        self.bytes.get(offset..end).ok_or(Ktx2Error::UnexpectedEof {
+            expected: end,
+            found: self.bytes.len(),
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Ktx2Error> {
+        let slice = self.slice(self.offset, len)?;
+        self.offset += len;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, Ktx2Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Ktx2Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Read a 64 bit offset or length
+    fn size(&mut self) -> Result<usize, Ktx2Error> {
+        usize::try_from(self.u64()?).map_err(|_| Ktx2Error::SizeOverflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mips::mip_size;
+    use crate::texture::{Texture, TextureBuilder};
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    /// Offset of the `layerCount` field of the header
+    const LAYER_COUNT: usize = KTX2_IDENTIFIER.len() + 5 * 4;
+    /// Offset of the `levelCount` field of the header
+    const LEVEL_COUNT: usize = KTX2_IDENTIFIER.len() + 7 * 4;
+
+    /// Encode a texture with a different gradient in every layer
+    fn texture(format: ImageFormat, layout: TextureLayout, size: u32, mips: u32) -> Texture {
+        let images = (0..layout.layers()).map(|layer| {
+            DynamicImage::ImageRgba8(RgbaImage::from_fn(size, size, |x, y| {
+                Rgba([
+                    (x * 255 / size) as u8,
+                    (y * 255 / size) as u8,
+                    (layer * 40) as u8,
+                    255 - (x * y) as u8,
+                ])
+            }))
+        });
+        TextureBuilder::new(format)
+            .images(images)
+            .layout(layout)
+            .mips(mips)
+            .build()
+            .unwrap()
+    }
+
+    fn write(texture: &Texture, supercompression: Supercompression) -> Vec<u8> {
+        let mut bytes = vec![];
+        texture.write_ktx2(&mut bytes, supercompression).unwrap();
+        bytes
+    }
+
+    /// Write a texture, read it back and check that every surface survived
+    fn round_trip(texture: &Texture, supercompression: Supercompression) {
+        let file = Ktx2File::from_bytes(&write(texture, supercompression)).unwrap();
+        assert_eq!(file.format, texture.format);
+        assert_eq!((file.width, file.height), (texture.width, texture.height));
+        assert_eq!(file.layout, texture.layout);
+        assert_eq!(file.surfaces.len(), texture.surfaces.len());
+        for surface in &file.surfaces {
+            assert_eq!(
+                (surface.width, surface.height),
+                mip_size(texture.width, texture.height, surface.mip)
+            );
+            assert_eq!(
+                Some(surface.data.as_slice()),
+                texture.surface(surface.layer, surface.mip)
+            );
+        }
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn mip_chain_round_trip() {
+        let uncompressed = texture(ImageFormat::Rgba8, TextureLayout::Texture2D, 16, u32::MAX);
+        round_trip(&uncompressed, Supercompression::None);
+        round_trip(&uncompressed, Supercompression::Zstd(3));
+
+        let compressed = texture(
+            ImageFormat::Rgba8_Bc3,
+            TextureLayout::Texture2D,
+            16,
+            u32::MAX,
+        );
+        round_trip(&compressed, Supercompression::None);
+    }
+
+    #[test]
+    fn cubemap_round_trip() {
+        let texture = texture(ImageFormat::Rgba8_Bc7, TextureLayout::Cubemap, 8, 2);
+        round_trip(&texture, Supercompression::None);
+        round_trip(&texture, Supercompression::Zstd(3));
+    }
+
+    #[test]
+    fn array_round_trip() {
+        let texture = texture(ImageFormat::R11_Eac, TextureLayout::Array(3), 8, 2);
+        round_trip(&texture, Supercompression::None);
+        round_trip(&texture, Supercompression::Zstd(3));
+    }
+
+    #[test]
+    fn truncated_file() {
+        let texture = texture(ImageFormat::Rgba8, TextureLayout::Texture2D, 4, 1);
+        let bytes = write(&texture, Supercompression::None);
+        let result = Ktx2File::from_bytes(&bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(Ktx2Error::UnexpectedEof { .. })));
+    }
+
+    #[test]
+    fn oversized_level_count() {
+        let texture = texture(ImageFormat::Rgba8, TextureLayout::Texture2D, 4, 1);
+        let mut bytes = write(&texture, Supercompression::None);
+        set_u32(&mut bytes, LEVEL_COUNT, 33);
+        let result = Ktx2File::from_bytes(&bytes);
+        assert!(matches!(result, Err(Ktx2Error::BadLevelCount(33))));
+    }
+
+    #[test]
+    fn cubemap_array() {
+        let texture = texture(ImageFormat::Rgba8, TextureLayout::Cubemap, 4, 1);
+        let mut bytes = write(&texture, Supercompression::None);
+        set_u32(&mut bytes, LAYER_COUNT, 2);
+        let result = Ktx2File::from_bytes(&bytes);
+        assert!(matches!(
+            result,
+            Err(Ktx2Error::UnsupportedLayout {
+                layers: 2,
+                faces: 6
+            })
+        ));
+    }
+}
@@ -1,32 +1,110 @@
+use crate::astc::AstcBlock;
+use crate::bcn::bc1::Bc1Block;
+use crate::bcn::bc2::Bc2Block;
+use crate::bcn::bc3::Bc3Block;
 use crate::bcn::bc4::Bc4Block;
 use crate::bcn::bc5::Bc5Block;
-use crate::bcn::bc7::Bc7Mode3Block;
+use crate::bcn::bc6h::Bc6hBlock;
+use crate::bcn::bc7::Bc7Block;
+use crate::color::ColorSpace;
+use crate::etc::eac::{EacBlock, Rg11Block};
+use crate::etc::etc2::{Etc2Block, Etc2RgbaBlock};
 use std::mem;
 
+pub use error::{Error, Result};
+
+pub mod astc;
+pub mod color;
+pub mod cubemap;
 pub mod dds;
+pub mod error;
+pub mod ktx2;
+pub mod metrics;
+pub mod mips;
+pub mod pack;
+pub mod texture;
 pub mod bcn {
+    pub mod bc1;
+    pub mod bc2;
+    pub mod bc3;
     pub mod bc4;
     pub mod bc5;
+    pub mod bc6h;
     pub mod bc7;
 
-    mod util;
+    mod quality;
+    pub(crate) mod util;
+
+    pub use quality::Quality;
+}
+pub mod etc {
+    pub mod eac;
+    pub mod etc2;
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum ImageFormat {
     Luma8,
     LumaAlpha8,
     #[default]
     Rgb8,
     Rgba8,
+    Srgba8,
+    /// 16 bit single channel, stores luma like `Luma8`, for heightmaps
+    R16,
+    /// 16 bit red and green
+    Rg16,
+    Rgba16,
+    /// Half float single channel, stores luma like `Luma8`
+    R16F,
+    /// Half float rgba, for HDR images
+    Rgba16F,
+    /// Full float rgba, for HDR data that needs more range or precision than half floats
+    Rgba32F,
+    Rgb8_Bc1,
+    Srgb8_Bc1,
+    /// Bc1 with 1 bit alpha
+    Rgba8_Bc1,
+    Srgba8_Bc1,
+    /// Bc2 with explicit 4 bit alpha, only kept around for legacy `DXT3` files
+    Rgba8_Bc2,
+    Srgba8_Bc2,
+    Rgba8_Bc3,
+    Srgba8_Bc3,
     Luma8_Bc4,
+    /// Signed Bc4, for height offsets and other values centered on zero
+    Luma8Snorm_Bc4,
     LumaAlpha8_Bc5,
     Rg8_Bc5,
+    /// Signed Bc5, mostly for normal maps
+    Rg8Snorm_Bc5,
     //Luma16_Bc5,
-    //Rgba8_Bc7,
     Rgb8_Bc7,
-    //RgbF_Bc6,
-    //RgbUF_Bc6,
+    Srgb8_Bc7,
+    Rgba8_Bc7,
+    Srgba8_Bc7,
+    /// Signed half float Bc6h
+    RgbF_Bc6,
+    /// Unsigned half float Bc6h
+    RgbUF_Bc6,
+    Rgb8_Etc2,
+    Srgb8_Etc2,
+    /// Etc2 with Eac alpha
+    Rgba8_Etc2,
+    Srgba8_Etc2,
+    /// Single channel Eac with 11 bits of precision, stores luma like Bc4
+    R11_Eac,
+    /// Two channel Eac with 11 bits of precision
+    Rg11_Eac,
+    /// Astc with 4x4 blocks, 8 bits per pixel
+    Rgba8_Astc4x4,
+    Srgba8_Astc4x4,
+    /// Astc with 6x6 blocks, 3.56 bits per pixel
+    Rgba8_Astc6x6,
+    Srgba8_Astc6x6,
+    /// Astc with 8x8 blocks, 2 bits per pixel
+    Rgba8_Astc8x8,
+    Srgba8_Astc8x8,
 }
 
 impl ImageFormat {
@@ -35,31 +113,173 @@ impl ImageFormat {
             ImageFormat::Luma8 => 8,
             ImageFormat::LumaAlpha8 => 8 * 2,
             ImageFormat::Rgb8 => 8 * 3,
-            ImageFormat::Rgba8 => 8 * 4,
+            ImageFormat::Rgba8 | ImageFormat::Srgba8 => 8 * 4,
+            ImageFormat::R16 | ImageFormat::R16F => 16,
+            ImageFormat::Rg16 => 16 * 2,
+            ImageFormat::Rgba16 | ImageFormat::Rgba16F => 16 * 4,
+            ImageFormat::Rgba32F => 32 * 4,
             _ => 0,
         }
     }
 
     pub fn block_size(&self) -> Option<u32> {
         match self {
-            ImageFormat::Luma8_Bc4 => Some(mem::size_of::<Bc4Block>() as u32),
-            ImageFormat::LumaAlpha8_Bc5 | ImageFormat::Rg8_Bc5 => {
+            ImageFormat::Rgb8_Bc1
+            | ImageFormat::Srgb8_Bc1
+            | ImageFormat::Rgba8_Bc1
+            | ImageFormat::Srgba8_Bc1 => Some(mem::size_of::<Bc1Block>() as u32),
+            ImageFormat::Rgba8_Bc2 | ImageFormat::Srgba8_Bc2 => {
+                Some(mem::size_of::<Bc2Block>() as u32)
+            }
+            ImageFormat::Rgba8_Bc3 | ImageFormat::Srgba8_Bc3 => {
+                Some(mem::size_of::<Bc3Block>() as u32)
+            }
+            ImageFormat::Luma8_Bc4 | ImageFormat::Luma8Snorm_Bc4 => {
+                Some(mem::size_of::<Bc4Block>() as u32)
+            }
+            ImageFormat::LumaAlpha8_Bc5 | ImageFormat::Rg8_Bc5 | ImageFormat::Rg8Snorm_Bc5 => {
                 Some(mem::size_of::<Bc5Block>() as u32)
             }
-            ImageFormat::Rgb8_Bc7 => Some(mem::size_of::<Bc7Mode3Block>() as u32),
+            ImageFormat::Rgb8_Bc7
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::Rgba8_Bc7
+            | ImageFormat::Srgba8_Bc7 => Some(mem::size_of::<Bc7Block>() as u32),
+            ImageFormat::RgbF_Bc6 | ImageFormat::RgbUF_Bc6 => {
+                Some(mem::size_of::<Bc6hBlock>() as u32)
+            }
+            ImageFormat::Rgb8_Etc2 | ImageFormat::Srgb8_Etc2 => {
+                Some(mem::size_of::<Etc2Block>() as u32)
+            }
+            ImageFormat::Rgba8_Etc2 | ImageFormat::Srgba8_Etc2 => {
+                Some(mem::size_of::<Etc2RgbaBlock>() as u32)
+            }
+            ImageFormat::R11_Eac => Some(mem::size_of::<EacBlock>() as u32),
+            ImageFormat::Rg11_Eac => Some(mem::size_of::<Rg11Block>() as u32),
+            ImageFormat::Rgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Rgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Rgba8_Astc8x8
+            | ImageFormat::Srgba8_Astc8x8 => Some(mem::size_of::<AstcBlock>() as u32),
             _ => None,
         }
     }
 
     pub fn is_compressed(&self) -> bool {
         match self {
-            ImageFormat::LumaAlpha8_Bc5
+            ImageFormat::Rgb8_Bc1
+            | ImageFormat::Srgb8_Bc1
+            | ImageFormat::Rgba8_Bc1
+            | ImageFormat::Srgba8_Bc1
+            | ImageFormat::Rgba8_Bc2
+            | ImageFormat::Srgba8_Bc2
+            | ImageFormat::Rgba8_Bc3
+            | ImageFormat::Srgba8_Bc3
+            | ImageFormat::LumaAlpha8_Bc5
             | ImageFormat::Luma8_Bc4
+            | ImageFormat::Luma8Snorm_Bc4
             | ImageFormat::Rg8_Bc5
-            | ImageFormat::Rgb8_Bc7 => true,
+            | ImageFormat::Rg8Snorm_Bc5
+            | ImageFormat::Rgb8_Bc7
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::Rgba8_Bc7
+            | ImageFormat::Srgba8_Bc7
+            | ImageFormat::RgbF_Bc6
+            | ImageFormat::RgbUF_Bc6
+            | ImageFormat::Rgb8_Etc2
+            | ImageFormat::Srgb8_Etc2
+            | ImageFormat::Rgba8_Etc2
+            | ImageFormat::Srgba8_Etc2
+            | ImageFormat::R11_Eac
+            | ImageFormat::Rg11_Eac
+            | ImageFormat::Rgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Rgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Rgba8_Astc8x8
+            | ImageFormat::Srgba8_Astc8x8 => true,
             _ => false,
         }
     }
+
+    /// Width and height in texels of a single block, 1x1 for uncompressed formats
+    pub fn block_footprint(&self) -> (u32, u32) {
+        match self {
+            ImageFormat::Rgba8_Astc6x6 | ImageFormat::Srgba8_Astc6x6 => (6, 6),
+            ImageFormat::Rgba8_Astc8x8 | ImageFormat::Srgba8_Astc8x8 => (8, 8),
+            _ if self.is_compressed() => (4, 4),
+            _ => (1, 1),
+        }
+    }
+
+    /// Color space the color channels of this format are stored in
+    pub fn color_space(&self) -> ColorSpace {
+        match self {
+            ImageFormat::Srgba8
+            | ImageFormat::Srgb8_Bc1
+            | ImageFormat::Srgba8_Bc1
+            | ImageFormat::Srgba8_Bc2
+            | ImageFormat::Srgba8_Bc3
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::Srgba8_Bc7
+            | ImageFormat::Srgb8_Etc2
+            | ImageFormat::Srgba8_Etc2
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc8x8 => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        }
+    }
+
+    /// The same format with its color channels stored in another color space
+    ///
+    /// returns `None` if the format has no variant for that color space
+    pub fn with_color_space(&self, color_space: ColorSpace) -> Option<Self> {
+        const PAIRS: [(ImageFormat, ImageFormat); 12] = [
+            (ImageFormat::Rgba8, ImageFormat::Srgba8),
+            (ImageFormat::Rgb8_Bc1, ImageFormat::Srgb8_Bc1),
+            (ImageFormat::Rgba8_Bc1, ImageFormat::Srgba8_Bc1),
+            (ImageFormat::Rgba8_Bc2, ImageFormat::Srgba8_Bc2),
+            (ImageFormat::Rgba8_Bc3, ImageFormat::Srgba8_Bc3),
+            (ImageFormat::Rgb8_Bc7, ImageFormat::Srgb8_Bc7),
+            (ImageFormat::Rgba8_Bc7, ImageFormat::Srgba8_Bc7),
+            (ImageFormat::Rgb8_Etc2, ImageFormat::Srgb8_Etc2),
+            (ImageFormat::Rgba8_Etc2, ImageFormat::Srgba8_Etc2),
+            (ImageFormat::Rgba8_Astc4x4, ImageFormat::Srgba8_Astc4x4),
+            (ImageFormat::Rgba8_Astc6x6, ImageFormat::Srgba8_Astc6x6),
+            (ImageFormat::Rgba8_Astc8x8, ImageFormat::Srgba8_Astc8x8),
+        ];
+        if self.color_space() == color_space {
+            return Some(*self);
+        }
+        PAIRS.iter().find_map(|(linear, srgb)| match color_space {
+            ColorSpace::Linear => (srgb == self).then_some(*linear),
+            ColorSpace::Srgb => (linear == self).then_some(*srgb),
+        })
+    }
+}
+
+/// How the surfaces of a texture are arranged
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextureLayout {
+    /// A single 2D surface
+    #[default]
+    Texture2D,
+    /// A 2D texture array with the given number of layers
+    Array(u32),
+    /// A cube map made of 6 faces in the order +X, -X, +Y, -Y, +Z, -Z
+    Cubemap,
+}
+
+impl TextureLayout {
+    /// Number of 2D surfaces (before mipmapping) stored in a texture with this layout
+    pub fn layers(&self) -> u32 {
+        match self {
+            TextureLayout::Texture2D => 1,
+            TextureLayout::Array(layers) => *layers,
+            TextureLayout::Cubemap => 6,
+        }
+    }
 }
 
 // TODO: Start on texture converter.
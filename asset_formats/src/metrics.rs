@@ -0,0 +1,284 @@
+//! Quality metrics comparing an encoded texture against its source image
+//!
+//! Every metric works on normalized channel values, so 8 bit and float images can be compared the
+//! same way.
+
+use crate::error::{Error, Result};
+use crate::ImageFormat;
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+use std::fmt::{Display, Formatter};
+
+/// Width and height of the windows SSIM is measured over
+const SSIM_WINDOW: u32 = 8;
+/// Distance between neighbouring SSIM windows
+const SSIM_STRIDE: u32 = 4;
+/// Per pixel error that maps to the hottest color of an error map, 32 steps of an 8 bit channel
+const HEAT_MAP_RANGE: f32 = 32.0 / 255.0;
+
+/// A single channel of an image that can be compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Rec. 709 luma of the color channels
+    Luma,
+}
+
+impl Channel {
+    /// Short name used when printing metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Red => "R",
+            Channel::Green => "G",
+            Channel::Blue => "B",
+            Channel::Alpha => "A",
+            Channel::Luma => "L",
+        }
+    }
+
+    /// Pull the normalized values of this channel out of an image
+    pub(crate) fn plane(&self, img: &DynamicImage) -> Vec<f32> {
+        match self {
+            Channel::Luma => img.to_luma32f().into_raw(),
+            _ => {
+                let index = match self {
+                    Channel::Red => 0,
+                    Channel::Green => 1,
+                    Channel::Blue => 2,
+                    _ => 3,
+                };
+                img.to_rgba32f().pixels().map(|p| p.0[index]).collect()
+            }
+        }
+    }
+}
+
+/// Metrics of a single channel
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelMetrics {
+    pub channel: Channel,
+    /// Root mean squared error of the normalized values
+    pub rmse: f64,
+    /// Peak signal to noise ratio in dB, infinite when both images are identical
+    pub psnr: f64,
+    /// Mean structural similarity, 1 when both images are identical
+    pub ssim: f64,
+}
+
+/// Metrics of every compared channel of an image
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub channels: Vec<ChannelMetrics>,
+}
+
+impl Metrics {
+    /// Root mean squared error over all channels
+    pub fn rmse(&self) -> f64 {
+        let mse = self.channels.iter().map(|c| c.rmse * c.rmse).sum::<f64>();
+        (mse / self.channels.len().max(1) as f64).sqrt()
+    }
+}
+
+impl Display for ChannelMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: rmse {:.5} ({:.2} / 255), psnr {:.2} dB, ssim {:.5}",
+            self.channel.name(),
+            self.rmse,
+            self.rmse * 255.0,
+            self.psnr,
+            self.ssim
+        )
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, channel) in self.channels.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{channel}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare a decoded image against the image it was encoded from
+///
+/// The peak value used for PSNR and SSIM is 1, or the brightest value of a channel for HDR images.
+/// Fails if the images aren't the same size.
+///
+/// * `reference`: Source image
+/// * `decoded`: Image decoded from the encoded texture, must be the same size as `reference`
+/// * `channels`: Channels to compare
+pub fn compare(
+    reference: &DynamicImage,
+    decoded: &DynamicImage,
+    channels: &[Channel],
+) -> Result<Metrics> {
+    check_size(reference, decoded)?;
+    let (width, height) = reference.dimensions();
+    let channels = channels
+        .iter()
+        .map(|&channel| {
+            let a = channel.plane(reference);
+            let b = channel.plane(decoded);
+            let peak = a.iter().fold(1.0f32, |peak, v| peak.max(*v)) as f64;
+
+            let mse = a
+                .iter()
+                .zip(&b)
+                .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+                .sum::<f64>()
+                / a.len().max(1) as f64;
+            let psnr = if mse == 0.0 {
+                f64::INFINITY
+            } else {
+                10.0 * (peak * peak / mse).log10()
+            };
+            ChannelMetrics {
+                channel,
+                rmse: mse.sqrt(),
+                psnr,
+                ssim: ssim(&a, &b, width, height, peak),
+            }
+        })
+        .collect();
+    Ok(Metrics { channels })
+}
+
+/// Make sure two compared images are the same size
+fn check_size(reference: &DynamicImage, decoded: &DynamicImage) -> Result<()> {
+    if reference.dimensions() != decoded.dimensions() {
+        return Err(Error::MismatchedSize {
+            expected: reference.dimensions(),
+            found: decoded.dimensions(),
+        });
+    }
+    Ok(())
+}
+
+/// Mean SSIM of overlapping square windows, images smaller than a window are a single window
+fn ssim(a: &[f32], b: &[f32], width: u32, height: u32, peak: f64) -> f64 {
+    let c1 = (0.01 * peak).powi(2);
+    let c2 = (0.03 * peak).powi(2);
+    let (window_w, window_h) = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in (0..=height - window_h).step_by(SSIM_STRIDE as usize) {
+        for x in (0..=width - window_w).step_by(SSIM_STRIDE as usize) {
+            let texels = (y..y + window_h)
+                .flat_map(|y| (x..x + window_w).map(move |x| (y * width + x) as usize));
+            let n = (window_w * window_h) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for i in texels {
+                let (a, b) = (a[i] as f64, b[i] as f64);
+                sum_a += a;
+                sum_b += b;
+                sum_aa += a * a;
+                sum_bb += b * b;
+                sum_ab += a * b;
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covar = sum_ab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covar + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            windows += 1;
+        }
+    }
+    total / windows.max(1) as f64
+}
+
+/// Build a heat map of the per pixel error between two images
+///
+/// The error of each pixel is the root mean square over the compared channels. It goes from black
+/// through red and yellow to white, which is reached at an error of 32 steps of an 8 bit channel.
+/// Fails if the images aren't the same size.
+pub fn error_map(
+    reference: &DynamicImage,
+    decoded: &DynamicImage,
+    channels: &[Channel],
+) -> Result<RgbImage> {
+    check_size(reference, decoded)?;
+    let (width, height) = reference.dimensions();
+    let mut error = vec![0.0; (width * height) as usize];
+    for channel in channels {
+        let a = channel.plane(reference);
+        let b = channel.plane(decoded);
+        for (e, (a, b)) in error.iter_mut().zip(a.iter().zip(&b)) {
+            *e += (a - b).powi(2) / channels.len() as f32;
+        }
+    }
+
+    let mut img = RgbImage::new(width, height);
+    for (pixel, e) in img.pixels_mut().zip(error) {
+        *pixel = heat(e.sqrt() / HEAT_MAP_RANGE);
+    }
+    Ok(img)
+}
+
+/// Color of a value on the heat map ramp, values above 1 are clamped to white
+fn heat(value: f32) -> Rgb<u8> {
+    let v = value.clamp(0.0, 1.0) * 3.0;
+    let ramp = |start: f32| ((v - start).clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgb([ramp(0.0), ramp(1.0), ramp(2.0)])
+}
+
+impl ImageFormat {
+    /// Channels of the source image that are stored in this format
+    pub fn channels(&self) -> &'static [Channel] {
+        use Channel::*;
+        match self {
+            ImageFormat::Luma8
+            | ImageFormat::Luma8_Bc4
+            | ImageFormat::Luma8Snorm_Bc4
+            | ImageFormat::R11_Eac
+            | ImageFormat::R16
+            | ImageFormat::R16F => &[Luma],
+            ImageFormat::LumaAlpha8 | ImageFormat::LumaAlpha8_Bc5 => &[Luma, Alpha],
+            ImageFormat::Rg8_Bc5
+            | ImageFormat::Rg8Snorm_Bc5
+            | ImageFormat::Rg11_Eac
+            | ImageFormat::Rg16 => &[Red, Green],
+            ImageFormat::Rgb8
+            | ImageFormat::Rgb8_Bc1
+            | ImageFormat::Srgb8_Bc1
+            | ImageFormat::Rgb8_Bc7
+            | ImageFormat::Srgb8_Bc7
+            | ImageFormat::RgbF_Bc6
+            | ImageFormat::RgbUF_Bc6
+            | ImageFormat::Rgb8_Etc2
+            | ImageFormat::Srgb8_Etc2 => &[Red, Green, Blue],
+            ImageFormat::Rgba8
+            | ImageFormat::Srgba8
+            | ImageFormat::Rgba16
+            | ImageFormat::Rgba16F
+            | ImageFormat::Rgba32F
+            | ImageFormat::Rgba8_Bc1
+            | ImageFormat::Srgba8_Bc1
+            | ImageFormat::Rgba8_Bc2
+            | ImageFormat::Srgba8_Bc2
+            | ImageFormat::Rgba8_Bc3
+            | ImageFormat::Srgba8_Bc3
+            | ImageFormat::Rgba8_Bc7
+            | ImageFormat::Srgba8_Bc7
+            | ImageFormat::Rgba8_Etc2
+            | ImageFormat::Srgba8_Etc2
+            | ImageFormat::Rgba8_Astc4x4
+            | ImageFormat::Srgba8_Astc4x4
+            | ImageFormat::Rgba8_Astc6x6
+            | ImageFormat::Srgba8_Astc6x6
+            | ImageFormat::Rgba8_Astc8x8
+            | ImageFormat::Srgba8_Astc8x8 => &[Red, Green, Blue, Alpha],
+        }
+    }
+}
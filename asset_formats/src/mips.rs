@@ -0,0 +1,121 @@
+//! Mipmap chain generation
+
+use crate::color::{self, ColorSpace};
+use glam::Vec3;
+use image::imageops::FilterType;
+use image::{imageops, DynamicImage, Rgba, Rgba32FImage};
+
+/// Filter used to downsample each level of a mip chain
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Average each 2x2 square of the previous level
+    #[default]
+    Box,
+    /// Bilinear filter from the full size image
+    Triangle,
+    /// Lanczos filter with a window of 3 from the full size image
+    Lanczos,
+}
+
+/// Number of levels in a full mip chain, down to and including a 1x1 level
+pub fn mip_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Size of a mip level, clamped to 1 pixel
+pub fn mip_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Generate a mip chain for an image
+///
+/// The first level is the source image itself, the rest are downsampled with `filter` and stored
+/// as 32 bit float images. Filtering always happens on linear values, so sRGB images are decoded
+/// first and each level is encoded back to sRGB.
+///
+/// * `img`: Full size image
+/// * `filter`: Filter used to generate the smaller levels
+/// * `levels`: Number of levels to generate, clamped to the length of a full mip chain
+/// * `color_space`: Color space of the image's color channels
+pub fn generate(
+    img: &DynamicImage,
+    filter: MipFilter,
+    levels: u32,
+    color_space: ColorSpace,
+) -> Vec<DynamicImage> {
+    chain(
+        img,
+        filter,
+        levels,
+        |img| color::convert(img, color_space, ColorSpace::Linear),
+        |img| color::convert(img, ColorSpace::Linear, color_space),
+    )
+}
+
+/// Generate a mip chain for a tangent space normal map
+///
+/// Works like [`generate`], but the rgb channels hold normals mapped from -1..1 to 0..1. Filtering
+/// shortens the normals, so they are renormalized for every level.
+pub fn generate_normals(img: &DynamicImage, filter: MipFilter, levels: u32) -> Vec<DynamicImage> {
+    chain(img, filter, levels, |img| img.clone(), normalize)
+}
+
+/// Renormalize the normals stored in the rgb channels of an image
+fn normalize(img: &Rgba32FImage) -> Rgba32FImage {
+    let mut img = img.clone();
+    for Rgba(pixel) in img.pixels_mut() {
+        let normal = Vec3::new(pixel[0], pixel[1], pixel[2]) * 2.0 - 1.0;
+        let normal = normal.try_normalize().unwrap_or(Vec3::Z) * 0.5 + 0.5;
+        pixel[..3].copy_from_slice(&normal.to_array());
+    }
+    img
+}
+
+/// Build a mip chain, filtering happens on the values returned by `decode` and every level is
+/// passed through `encode` before it is stored
+fn chain(
+    img: &DynamicImage,
+    filter: MipFilter,
+    levels: u32,
+    decode: impl Fn(&Rgba32FImage) -> Rgba32FImage,
+    encode: impl Fn(&Rgba32FImage) -> Rgba32FImage,
+) -> Vec<DynamicImage> {
+    let levels = levels.clamp(1, mip_count(img.width(), img.height()));
+    let source = decode(&img.to_rgba32f());
+
+    let mut chain = vec![img.clone()];
+    let mut prev = source.clone();
+    for level in 1..levels {
+        let (w, h) = mip_size(img.width(), img.height(), level);
+        let next = match filter {
+            MipFilter::Box => downsample_box(&prev),
+            MipFilter::Triangle => imageops::resize(&source, w, h, FilterType::Triangle),
+            MipFilter::Lanczos => imageops::resize(&source, w, h, FilterType::Lanczos3),
+        };
+        let level = encode(&next);
+        chain.push(DynamicImage::ImageRgba32F(level));
+        prev = next;
+    }
+    chain
+}
+
+/// Halve an image by averaging each 2x2 square
+///
+/// The last row or column of an odd sized image is skipped, except for images that are a single
+/// pixel wide or tall.
+fn downsample_box(img: &Rgba32FImage) -> Rgba32FImage {
+    let (w, h) = mip_size(img.width(), img.height(), 1);
+    Rgba32FImage::from_fn(w, h, |x, y| {
+        let xs = [x * 2, (x * 2 + 1).min(img.width() - 1)];
+        let ys = [y * 2, (y * 2 + 1).min(img.height() - 1)];
+        let mut sum = [0.0f32; 4];
+        for y in ys {
+            for x in xs {
+                for (s, c) in sum.iter_mut().zip(img.get_pixel(x, y).0) {
+                    *s += c;
+                }
+            }
+        }
+        Rgba(sum.map(|s| s / 4.0))
+    })
+}
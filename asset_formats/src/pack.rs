@@ -0,0 +1,101 @@
+//! Packing channels of several images into a single image
+//!
+//! Material workflows usually produce a grayscale map for every property, packing them into the
+//! channels of one texture (like occlusion, roughness and metalness into red, green and blue)
+//! saves memory and texture fetches.
+
+use crate::error::Result;
+use crate::metrics::Channel;
+use crate::texture::TextureError;
+use image::{DynamicImage, GenericImageView, LumaA, Rgba, Rgba32FImage};
+
+/// Where the values of a packed channel come from
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// A channel of another image, [`Channel::Luma`] takes the luma of its color channels
+    Channel(DynamicImage, Channel),
+    /// The same normalized value for every pixel
+    Constant(f32),
+}
+
+/// Build an rgba image out of channels of other images
+///
+/// The packed image is 16 bits per channel, or 32 bit float if any of the images is a float
+/// image. Fails if there are no images to take the size from or the images aren't the same size.
+///
+/// * `base`: Image the channels without a source are copied from, without one they are 0 and
+///   alpha is 1
+/// * `sources`: Source of the red, green, blue and alpha channel
+/// * `invert`: Channels that are flipped to `1 - value` once they are packed
+pub fn pack(
+    base: Option<&DynamicImage>,
+    sources: &[Option<Source>; 4],
+    invert: [bool; 4],
+) -> Result<DynamicImage> {
+    let images =
+        base.into_iter()
+            .chain(sources.iter().flatten().filter_map(|source| match source {
+                Source::Channel(img, _) => Some(img),
+                Source::Constant(_) => None,
+            }));
+    let Some((width, height)) = images.clone().next().map(|img| img.dimensions()) else {
+        return Err(TextureError::NoImages.into());
+    };
+    if let Some(img) = images
+        .clone()
+        .find(|img| img.dimensions() != (width, height))
+    {
+        return Err(TextureError::MismatchedSize {
+            expected: (width, height),
+            found: img.dimensions(),
+        }
+        .into());
+    }
+    let float = images.into_iter().any(|img| {
+        matches!(
+            img,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        )
+    });
+
+    let mut packed = match base {
+        Some(img) => img.to_rgba32f(),
+        None => Rgba32FImage::from_pixel(width, height, Rgba([0.0, 0.0, 0.0, 1.0])),
+    };
+    for (c, source) in sources.iter().enumerate() {
+        match source {
+            Some(Source::Channel(img, channel)) => {
+                let plane = channel.plane(img);
+                for (pixel, value) in packed.pixels_mut().zip(plane) {
+                    pixel[c] = value;
+                }
+            }
+            Some(Source::Constant(value)) => packed.pixels_mut().for_each(|p| p[c] = *value),
+            None => {}
+        }
+    }
+    for pixel in packed.pixels_mut() {
+        for c in (0..4).filter(|c| invert[*c]) {
+            pixel[c] = 1.0 - pixel[c];
+        }
+    }
+
+    let packed = DynamicImage::ImageRgba32F(packed);
+    Ok(match float {
+        true => packed,
+        false => DynamicImage::ImageRgba16(packed.to_rgba16()),
+    })
+}
+
+/// Store the red channel of an image as the luma of a grayscale image, keeping its alpha
+///
+/// Luma formats are encoded from the luma of the color channels, so a packed single channel
+/// texture has to move its red channel into the luma first.
+pub fn red_to_luma(img: &DynamicImage) -> DynamicImage {
+    let rgba = img.to_rgba16();
+    let luma = image::ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let Rgba([r, _, _, a]) = *rgba.get_pixel(x, y);
+        LumaA([r, a])
+    });
+    DynamicImage::ImageLumaA16(luma)
+}
@@ -0,0 +1,565 @@
+//! Encoding images into textures that can be written to DDS or KTX2 files
+//!
+//! ```no_run
+//! # use asset_formats::texture::TextureBuilder;
+//! # use asset_formats::ImageFormat;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let img = image::open("albedo.png")?;
+//! let texture = TextureBuilder::new(ImageFormat::Srgba8_Bc7)
+//!     .image(img)
+//!     .mips(u32::MAX)
+//!     .build()?;
+//! texture.write_dds(std::fs::File::create("albedo.dds")?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::astc;
+use crate::bcn::{bc1, bc2, bc3, bc4, bc5, bc6h, bc7, Quality};
+use crate::color::{self, ColorSpace};
+use crate::dds::Dds;
+use crate::error::Result;
+use crate::etc::{eac, etc2};
+use crate::ktx2::{Ktx2File, Supercompression};
+use crate::mips::{self, MipFilter};
+use crate::{ImageFormat, TextureLayout};
+use half::f16;
+use image::{
+    DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, Luma, Rgb, Rgb32FImage, RgbImage, Rgba,
+    Rgba32FImage, RgbaImage,
+};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+/// Errors caused by images or surfaces that don't fit together into a texture
+#[derive(Debug)]
+pub enum TextureError {
+    /// The builder was not given any images
+    NoImages,
+    /// The number of images doesn't match the layers of the texture's layout
+    LayerCount { expected: u32, found: usize },
+    /// An image is not the same size as the first one
+    MismatchedSize {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// An image has a width or height of 0
+    EmptyImage,
+    /// Cube map faces have to be square
+    NonSquareCubemap { width: u32, height: u32 },
+    /// The format can't be stored in a DDS file with the texture's layout
+    DdsUnsupported(ImageFormat),
+    /// The number of surfaces isn't a whole mip chain for every layer of the texture's layout
+    SurfaceCount { layers: u32, found: usize },
+    /// The data of a surface doesn't match its size and format
+    SurfaceSize { expected: usize, found: usize },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::NoImages => write!(f, "a texture needs at least one image"),
+            TextureError::LayerCount { expected, found } => {
+                write!(f, "texture needs {expected} images but {found} were given")
+            }
+            TextureError::MismatchedSize { expected, found } => write!(
+                f,
+                "every image of a texture must be the same size, expected {}x{} but got {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            TextureError::EmptyImage => write!(f, "images must be at least 1x1"),
+            TextureError::NonSquareCubemap { width, height } => {
+                write!(f, "cube map faces must be square, got {width}x{height}")
+            }
+            TextureError::DdsUnsupported(format) => {
+                write!(f, "{format:?} can't be stored in a dds file")
+            }
+            TextureError::SurfaceCount { layers, found } => write!(
+                f,
+                "{found} surfaces can't be split into mip chains for {layers} layers"
+            ),
+            TextureError::SurfaceSize { expected, found } => {
+                write!(f, "surface needs {expected} bytes but holds {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// Builds a [`Texture`] by encoding images and their mip levels
+#[derive(Debug, Clone)]
+pub struct TextureBuilder {
+    format: ImageFormat,
+    images: Vec<DynamicImage>,
+    layout: Option<TextureLayout>,
+    mips: u32,
+    mip_filter: MipFilter,
+    normal_map: bool,
+    quality: Quality,
+}
+
+impl TextureBuilder {
+    /// Start a texture that is stored in `format`
+    pub fn new(format: ImageFormat) -> Self {
+        Self {
+            format,
+            images: vec![],
+            layout: None,
+            mips: 1,
+            mip_filter: MipFilter::default(),
+            normal_map: false,
+            quality: Quality::default(),
+        }
+    }
+
+    /// Add a layer (or cube face) to the texture
+    pub fn image(mut self, img: DynamicImage) -> Self {
+        self.images.push(img);
+        self
+    }
+
+    /// Add several layers (or cube faces) to the texture
+    pub fn images(mut self, images: impl IntoIterator<Item = DynamicImage>) -> Self {
+        self.images.extend(images);
+        self
+    }
+
+    /// Arrangement of the layers, by default a single image is a [`TextureLayout::Texture2D`] and
+    /// several images are a [`TextureLayout::Array`]
+    pub fn layout(mut self, layout: TextureLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Number of mip levels to generate for each layer, clamped to the length of a full mip chain
+    /// so `u32::MAX` generates every level
+    pub fn mips(mut self, mips: u32) -> Self {
+        self.mips = mips;
+        self
+    }
+
+    /// Filter used to downsample mip levels
+    pub fn mip_filter(mut self, filter: MipFilter) -> Self {
+        self.mip_filter = filter;
+        self
+    }
+
+    /// Treat the images as tangent space normal maps
+    ///
+    /// Mip levels are renormalized, and Bc5 formats store the X and Y of each normal.
+    pub fn normal_map(mut self, normal_map: bool) -> Self {
+        self.normal_map = normal_map;
+        self
+    }
+
+    /// Effort spent on the endpoints of Bc1 to Bc5 and Bc7 blocks, other formats ignore it
+    pub fn quality(mut self, quality: Quality) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Encode every mip level of every layer
+    pub fn build(self) -> Result<Texture> {
+        let layout = self.layout.unwrap_or(match self.images.len() {
+            1 => TextureLayout::Texture2D,
+            layers => TextureLayout::Array(layers as u32),
+        });
+        let first = self.images.first().ok_or(TextureError::NoImages)?;
+        if self.images.len() != layout.layers() as usize {
+            return Err(TextureError::LayerCount {
+                expected: layout.layers(),
+                found: self.images.len(),
+            }
+            .into());
+        }
+        let (width, height) = (first.width(), first.height());
+        if width == 0 || height == 0 {
+            return Err(TextureError::EmptyImage.into());
+        }
+        if let Some(img) = self
+            .images
+            .iter()
+            .find(|img| img.width() != width || img.height() != height)
+        {
+            return Err(TextureError::MismatchedSize {
+                expected: (width, height),
+                found: (img.width(), img.height()),
+            }
+            .into());
+        }
+        if layout == TextureLayout::Cubemap && width != height {
+            return Err(TextureError::NonSquareCubemap { width, height }.into());
+        }
+
+        let mips = self.mips.clamp(1, mips::mip_count(width, height));
+        let mut surfaces = vec![];
+        for img in self.images {
+            // float images hold linear values, they need to be encoded before being stored as sRGB
+            let img = match (self.format.color_space(), img) {
+                (
+                    ColorSpace::Srgb,
+                    img @ (DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)),
+                ) => {
+                    let linear = img.to_rgba32f();
+                    let srgb = color::convert(&linear, ColorSpace::Linear, ColorSpace::Srgb);
+                    DynamicImage::ImageRgba32F(srgb)
+                }
+                (_, img) => img,
+            };
+            let img = &img;
+            let levels = if self.normal_map {
+                mips::generate_normals(img, self.mip_filter, mips)
+            } else {
+                mips::generate(img, self.mip_filter, mips, self.format.color_space())
+            };
+            for level in levels {
+                surfaces.push(encode_surface(
+                    &level,
+                    self.format,
+                    self.normal_map,
+                    self.quality,
+                )?);
+            }
+        }
+
+        Ok(Texture {
+            format: self.format,
+            width,
+            height,
+            layout,
+            surfaces,
+        })
+    }
+}
+
+/// An encoded texture, ready to be written to a file
+#[derive(Debug, Clone)]
+pub struct Texture {
+    /// Format of the surfaces
+    pub format: ImageFormat,
+    /// Width of the full size surfaces
+    pub width: u32,
+    /// Height of the full size surfaces
+    pub height: u32,
+    pub layout: TextureLayout,
+    /// Data of every surface, ordered by layer and then by mip level
+    pub surfaces: Vec<Vec<u8>>,
+}
+
+impl Texture {
+    /// Number of mip levels stored for each layer
+    pub fn mip_levels(&self) -> u32 {
+        (self.surfaces.len() as u32 / self.layout.layers()).max(1)
+    }
+
+    /// Find the data of the surface for a given layer and mip level
+    pub fn surface(&self, layer: u32, mip: u32) -> Option<&[u8]> {
+        if layer >= self.layout.layers() || mip >= self.mip_levels() {
+            return None;
+        }
+        let index = layer * self.mip_levels() + mip;
+        self.surfaces.get(index as usize).map(Vec::as_slice)
+    }
+
+    /// Decode the surface for a given layer and mip level back into an image
+    pub fn decode(&self, layer: u32, mip: u32) -> Option<Result<DynamicImage>> {
+        let (width, height) = mips::mip_size(self.width, self.height, mip);
+        self.surface(layer, mip)
+            .map(|data| decode_surface(data, self.format, width, height))
+    }
+
+    /// Wrap the texture in a DDS file, see [`Dds::new`]
+    pub fn to_dds(&self) -> Result<Dds> {
+        Ok(Dds::new(
+            self.surfaces.clone(),
+            self.width,
+            self.height,
+            self.format,
+            self.layout,
+        )?)
+    }
+
+    /// Wrap the texture in a KTX2 file
+    pub fn to_ktx2(&self, supercompression: Supercompression) -> Result<Ktx2File> {
+        Ok(Ktx2File::new(
+            self.format,
+            self.width,
+            self.height,
+            self.layout,
+            supercompression,
+            self.surfaces.clone(),
+        )?)
+    }
+
+    /// Write the texture as a DDS file
+    pub fn write_dds(&self, writer: impl Write) -> Result<()> {
+        Ok(self.to_dds()?.write(writer)?)
+    }
+
+    /// Write the texture as a KTX2 file
+    pub fn write_ktx2(&self, writer: impl Write, supercompression: Supercompression) -> Result<()> {
+        Ok(self.to_ktx2(supercompression)?.write(writer)?)
+    }
+}
+
+/// Check that surfaces, ordered by layer and then by mip level, make up a texture
+///
+/// * `width`: Width of the full size surfaces
+/// * `height`: Height of the full size surfaces
+///
+/// returns the number of mip levels of each layer
+pub(crate) fn check_surfaces(
+    surfaces: &[Vec<u8>],
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    layout: TextureLayout,
+) -> std::result::Result<u32, TextureError> {
+    let layers = layout.layers();
+    let mips = surfaces.len() as u32 / layers;
+    if mips == 0
+        || !surfaces.len().is_multiple_of(layers as usize)
+        || mips > mips::mip_count(width, height)
+    {
+        return Err(TextureError::SurfaceCount {
+            layers,
+            found: surfaces.len(),
+        });
+    }
+    for (i, surface) in surfaces.iter().enumerate() {
+        let (width, height) = mips::mip_size(width, height, i as u32 % mips);
+        let expected = format.surface_size(width, height);
+        if surface.len() != expected {
+            return Err(TextureError::SurfaceSize {
+                expected,
+                found: surface.len(),
+            });
+        }
+    }
+    Ok(mips)
+}
+
+/// Convert a single surface into the bytes of a given format
+///
+/// * `normal_map`: The image is a tangent space normal map
+/// * `quality`: Effort spent on the endpoints of Bc1 to Bc5 and Bc7 blocks
+pub fn encode_surface(
+    img: &DynamicImage,
+    format: ImageFormat,
+    normal_map: bool,
+    quality: Quality,
+) -> Result<Vec<u8>> {
+    let color_space = format.color_space();
+    let data = match format {
+        ImageFormat::Rgb8 => img.to_rgb8().to_vec(),
+        ImageFormat::Rgba8 | ImageFormat::Srgba8 => img.to_rgba8().to_vec(),
+        ImageFormat::Luma8 => img.to_luma8().to_vec(),
+        ImageFormat::LumaAlpha8 => img.to_luma_alpha8().to_vec(),
+        ImageFormat::R16 => bytemuck::cast_slice(&img.to_luma16()).to_vec(),
+        ImageFormat::Rg16 => {
+            let rg: Vec<u16> = img.to_rgb16().pixels().flat_map(|p| [p[0], p[1]]).collect();
+            bytemuck::cast_slice(&rg).to_vec()
+        }
+        ImageFormat::Rgba16 => bytemuck::cast_slice(&img.to_rgba16()).to_vec(),
+        ImageFormat::R16F => to_half(&img.to_luma32f()),
+        ImageFormat::Rgba16F => to_half(&img.to_rgba32f()),
+        ImageFormat::Rgba32F => bytemuck::cast_slice(&img.to_rgba32f()).to_vec(),
+        ImageFormat::Rgb8_Bc1 | ImageFormat::Srgb8_Bc1 => {
+            bytemuck::cast_slice(&bc1::encode(img, color_space, quality)).to_vec()
+        }
+        ImageFormat::Rgba8_Bc1 | ImageFormat::Srgba8_Bc1 => {
+            bytemuck::cast_slice(&bc1::encode_alpha(img, color_space, quality)).to_vec()
+        }
+        ImageFormat::Rgba8_Bc2 | ImageFormat::Srgba8_Bc2 => {
+            bytemuck::cast_slice(&bc2::encode(img, color_space, quality)).to_vec()
+        }
+        ImageFormat::Rgba8_Bc3 | ImageFormat::Srgba8_Bc3 => {
+            bytemuck::cast_slice(&bc3::encode(img, color_space, quality)).to_vec()
+        }
+        ImageFormat::Luma8_Bc4 => bytemuck::cast_slice(&bc4::encode(img, quality)).to_vec(),
+        ImageFormat::Luma8Snorm_Bc4 => {
+            bytemuck::cast_slice(&bc4::encode_signed(img, quality)).to_vec()
+        }
+        ImageFormat::LumaAlpha8_Bc5 => {
+            bytemuck::cast_slice(&bc5::encode_grayscale(img, quality)).to_vec()
+        }
+        ImageFormat::Rg8_Bc5 if normal_map => {
+            bytemuck::cast_slice(&bc5::encode_normals(img, false, quality)).to_vec()
+        }
+        ImageFormat::Rg8_Bc5 => bytemuck::cast_slice(&bc5::encode_color(img, quality)).to_vec(),
+        ImageFormat::Rg8Snorm_Bc5 if normal_map => {
+            bytemuck::cast_slice(&bc5::encode_normals(img, true, quality)).to_vec()
+        }
+        ImageFormat::Rg8Snorm_Bc5 => {
+            bytemuck::cast_slice(&bc5::encode_signed(img, quality)).to_vec()
+        }
+        ImageFormat::Rgb8_Bc7 | ImageFormat::Srgb8_Bc7 => {
+            bytemuck::cast_slice(&bc7::encode(img, color_space, quality)).to_vec()
+        }
+        ImageFormat::Rgba8_Bc7 | ImageFormat::Srgba8_Bc7 => {
+            bytemuck::cast_slice(&bc7::encode_alpha(img, color_space, quality)).to_vec()
+        }
+        ImageFormat::RgbUF_Bc6 => bytemuck::cast_slice(&bc6h::encode_unsigned(img)).to_vec(),
+        ImageFormat::RgbF_Bc6 => bytemuck::cast_slice(&bc6h::encode_signed(img)).to_vec(),
+        ImageFormat::Rgb8_Etc2 | ImageFormat::Srgb8_Etc2 => {
+            bytemuck::cast_slice(&etc2::encode(img, color_space)).to_vec()
+        }
+        ImageFormat::Rgba8_Etc2 | ImageFormat::Srgba8_Etc2 => {
+            bytemuck::cast_slice(&etc2::encode_alpha(img, color_space)).to_vec()
+        }
+        ImageFormat::R11_Eac => bytemuck::cast_slice(&eac::encode_r11(img)).to_vec(),
+        ImageFormat::Rg11_Eac => bytemuck::cast_slice(&eac::encode_rg11(img)).to_vec(),
+        ImageFormat::Rgba8_Astc4x4
+        | ImageFormat::Srgba8_Astc4x4
+        | ImageFormat::Rgba8_Astc6x6
+        | ImageFormat::Srgba8_Astc6x6
+        | ImageFormat::Rgba8_Astc8x8
+        | ImageFormat::Srgba8_Astc8x8 => {
+            let footprint = format.block_footprint();
+            bytemuck::cast_slice(&astc::encode(img, footprint, color_space)?).to_vec()
+        }
+    };
+    Ok(data)
+}
+
+/// Convert float channels to the bytes of half floats
+fn to_half(values: &[f32]) -> Vec<u8> {
+    let half: Vec<u16> = values.iter().map(|v| f16::from_f32(*v).to_bits()).collect();
+    bytemuck::cast_slice(&half).to_vec()
+}
+
+/// Convert the bytes of half floats back to float channels
+fn from_half(data: &[u8]) -> Vec<f32> {
+    let half: Vec<u16> = bytemuck::pod_collect_to_vec(data);
+    half.into_iter()
+        .map(|v| f16::from_bits(v).to_f32())
+        .collect()
+}
+
+/// Decode the bytes of a single surface back into an image
+///
+/// Signed formats are mapped back from -1..1 to 0..1, and normal maps only get their X and Y back.
+pub fn decode_surface(
+    data: &[u8],
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+) -> Result<DynamicImage> {
+    let expected = format.surface_size(width, height);
+    if data.len() < expected {
+        return Err(TextureError::SurfaceSize {
+            expected,
+            found: data.len(),
+        }
+        .into());
+    }
+    let data = data[..expected].to_vec();
+    let img = match format {
+        // the buffers are known to be large enough, so these can't fail
+        ImageFormat::Rgb8 => RgbImage::from_raw(width, height, data).unwrap().into(),
+        ImageFormat::Rgba8 | ImageFormat::Srgba8 => {
+            RgbaImage::from_raw(width, height, data).unwrap().into()
+        }
+        ImageFormat::Luma8 => GrayImage::from_raw(width, height, data).unwrap().into(),
+        ImageFormat::LumaAlpha8 => GrayAlphaImage::from_raw(width, height, data)
+            .unwrap()
+            .into(),
+        ImageFormat::R16 => {
+            let luma = bytemuck::pod_collect_to_vec(&data);
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, luma)
+                .unwrap()
+                .into()
+        }
+        ImageFormat::Rg16 => {
+            let rg: Vec<u16> = bytemuck::pod_collect_to_vec(&data);
+            let rgb = rg
+                .chunks_exact(2)
+                .flat_map(|rg| [rg[0], rg[1], 0])
+                .collect();
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, rgb)
+                .unwrap()
+                .into()
+        }
+        ImageFormat::Rgba16 => {
+            let rgba = bytemuck::pod_collect_to_vec(&data);
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, rgba)
+                .unwrap()
+                .into()
+        }
+        // there is no float grayscale image, luma is spread over rgb instead
+        ImageFormat::R16F => {
+            let rgb = from_half(&data).into_iter().flat_map(|v| [v; 3]).collect();
+            Rgb32FImage::from_raw(width, height, rgb).unwrap().into()
+        }
+        ImageFormat::Rgba16F => Rgba32FImage::from_raw(width, height, from_half(&data))
+            .unwrap()
+            .into(),
+        ImageFormat::Rgba32F => {
+            let rgba = bytemuck::pod_collect_to_vec(&data);
+            Rgba32FImage::from_raw(width, height, rgba).unwrap().into()
+        }
+        ImageFormat::Rgb8_Bc1
+        | ImageFormat::Srgb8_Bc1
+        | ImageFormat::Rgba8_Bc1
+        | ImageFormat::Srgba8_Bc1 => {
+            bc1::decode(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rgba8_Bc2 | ImageFormat::Srgba8_Bc2 => {
+            bc2::decode(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rgba8_Bc3 | ImageFormat::Srgba8_Bc3 => {
+            bc3::decode(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Luma8_Bc4 => bc4::decode(&bytemuck::pod_collect_to_vec(&data), width, height),
+        ImageFormat::LumaAlpha8_Bc5 => {
+            bc5::decode_grayscale(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rg8_Bc5 => {
+            bc5::decode_color(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Luma8Snorm_Bc4 => {
+            bc4::decode_signed(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rg8Snorm_Bc5 => {
+            bc5::decode_signed(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rgb8_Bc7
+        | ImageFormat::Srgb8_Bc7
+        | ImageFormat::Rgba8_Bc7
+        | ImageFormat::Srgba8_Bc7 => {
+            bc7::decode(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::RgbUF_Bc6 => {
+            bc6h::decode_unsigned(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::RgbF_Bc6 => {
+            bc6h::decode_signed(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rgb8_Etc2 | ImageFormat::Srgb8_Etc2 => {
+            etc2::decode(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rgba8_Etc2 | ImageFormat::Srgba8_Etc2 => {
+            etc2::decode_alpha(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::R11_Eac => {
+            eac::decode_r11(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rg11_Eac => {
+            eac::decode_rg11(&bytemuck::pod_collect_to_vec(&data), width, height)
+        }
+        ImageFormat::Rgba8_Astc4x4
+        | ImageFormat::Srgba8_Astc4x4
+        | ImageFormat::Rgba8_Astc6x6
+        | ImageFormat::Srgba8_Astc6x6
+        | ImageFormat::Rgba8_Astc8x8
+        | ImageFormat::Srgba8_Astc8x8 => astc::decode(
+            &bytemuck::pod_collect_to_vec(&data),
+            format.block_footprint(),
+            width,
+            height,
+            format.color_space(),
+        )?,
+    };
+    Ok(img)
+}
@@ -90,6 +90,18 @@ fn lookup_asset<T>(cache: &AssetCache<T>, id: &str) -> Option<Arc<T>> {
     None
 }
 
+/// Pick an sRGB format for a surface when one is supported
+///
+/// Shaders output linear colors, an sRGB surface encodes them for display.
+fn surface_format(surface: &wgpu::Surface, adapter: &wgpu::Adapter) -> wgpu::TextureFormat {
+    let formats = surface.get_capabilities(adapter).formats;
+    formats
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .unwrap_or(formats[0])
+}
+
 impl Surface {
     pub(crate) fn set_ui(
         &mut self,
@@ -100,11 +112,7 @@ impl Surface {
     }
 
     pub fn texture_format(&self) -> wgpu::TextureFormat {
-        self.surface
-            .as_ref()
-            .unwrap()
-            .get_capabilities(&self.adapter)
-            .formats[0]
+        surface_format(self.surface.as_ref().unwrap(), &self.adapter)
     }
 
     pub fn resume(&mut self) {
@@ -204,6 +212,7 @@ impl Surface {
         let size = self.win.inner_size();
         let config = wgpu::SurfaceConfiguration {
             present_mode: wgpu::PresentMode::AutoVsync,
+            format: surface_format(surface, &self.adapter),
             ..surface
                 .get_default_config(&self.adapter, size.width, size.height)
                 .unwrap()
@@ -244,7 +253,9 @@ impl Surface {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8Unorm,
+                // image files store sRGB encoded colors, sampling through an sRGB format
+                // hands shaders linear values
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
                 usage: TextureUsages::TEXTURE_BINDING,
                 view_formats: &[],
             },
@@ -288,14 +299,15 @@ impl Surface {
                 .unwrap();
 
             let size = win.inner_size();
+            let format = surface_format(&surface, &adapter);
             let config = wgpu::SurfaceConfiguration {
                 present_mode: wgpu::PresentMode::AutoVsync,
+                format,
                 ..surface
                     .get_default_config(&adapter, size.width, size.height)
                     .unwrap()
             };
             surface.configure(&device, &config);
-            let format = surface.get_capabilities(&adapter).formats[0];
             Surface {
                 adapter,
                 device,